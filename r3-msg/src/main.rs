@@ -1,11 +1,12 @@
 mod args;
 
 use std::error::Error;
-use std::io::{Read, Write};
+use std::io::{BufRead, BufReader, Read, Write};
 use std::net::Shutdown;
 use std::os::unix::net::UnixStream;
 
 use clap::Parser;
+use r3lib::{IpcErrorReply, R3Command};
 use xcb::x::{GetProperty, ATOM_ANY};
 use xcb::{x, Connection, ProtocolError};
 
@@ -22,17 +23,31 @@ pub fn main() -> Result<(), Box<dyn Error>> {
     let args = Args::parse();
 
     // If no socket path was provided, connect to X and look for the atom on the root window
-    let socket_path = args.socket.map_or_else(get_socket_path, |s| Ok(s))?;
+    let socket_path = args.socket.map_or_else(get_socket_path, Ok)?;
 
-    // TODO: right now r3 only supports a single command per read (reads until EOF)
-    //  we need to consider the case of long-lived IPC connections, and potentially buffer per line?
+    // r3 reads a single command per connection (until EOF)
     let mut conn = UnixStream::connect(socket_path)?;
     conn.write_all(&serde_json::to_vec(&args.command)?)?;
     conn.shutdown(Shutdown::Write)?;
 
+    // Events are sent one per line, for as long as r3 is running
+    if let R3Command::Subscribe = args.command {
+        for line in BufReader::new(conn).lines() {
+            println!("{}", line?);
+        }
+        return Ok(());
+    }
+
     // Read response
     let mut buffer = String::new();
     conn.read_to_string(&mut buffer)?;
+    if let Ok(IpcErrorReply { error }) = serde_json::from_str(&buffer) {
+        return Err(match (error.line, error.column) {
+            (Some(line), Some(column)) => format!("{} (at {}:{})", error.message, line, column),
+            _ => error.message,
+        }
+        .into());
+    }
     println!("response: {}", buffer);
 
     Ok(())
@@ -44,9 +59,7 @@ fn get_socket_path() -> Result<String, Box<dyn Error>> {
     let (conn, default_screen) = match Connection::connect(None) {
         Ok(inner) => inner,
         Err(_) => {
-            return Err(format!(
-                "Failed to connect to X. Ensure the DISPLAY environment variable is set."
-            ))?
+            return Err("Failed to connect to X. Ensure the DISPLAY environment variable is set.".into());
         }
     };
     let root_window = conn.get_setup().roots().nth(default_screen as usize).unwrap().root();
@@ -54,7 +67,7 @@ fn get_socket_path() -> Result<String, Box<dyn Error>> {
     // Fetch the Atom from the X server
     let atoms = Atoms::intern_all(&conn)?;
     if atoms.r3_socket_path == x::ATOM_NONE {
-        return Err("The X server isn't aware of R3_SOCKET_PATH. Is r3 running?")?;
+        return Err("The X server isn't aware of R3_SOCKET_PATH. Is r3 running?".into());
     }
 
     // Read the atom on the root X window
@@ -82,7 +95,7 @@ fn get_socket_path() -> Result<String, Box<dyn Error>> {
     };
 
     if value.is_empty() {
-        return Err("Found R3_SOCKET_PATH, but it was empty.")?;
+        return Err("Found R3_SOCKET_PATH, but it was empty.".into());
     }
 
     Ok(value)
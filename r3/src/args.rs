@@ -0,0 +1,39 @@
+use std::path::PathBuf;
+
+use clap_derive::Parser;
+use r3lib::LogLevel;
+
+#[derive(Debug, Parser)]
+#[clap(version)]
+pub struct Args {
+    /// Path to the configuration file
+    /// If not provided, $XDG_CONFIG_HOME/r3/config.toml is used
+    #[clap(long = "config", short = 'c')]
+    pub config: Option<PathBuf>,
+
+    /// The X display to manage
+    /// If not provided, the DISPLAY environment variable is used
+    #[clap(long = "display", short = 'd')]
+    pub display: Option<String>,
+
+    /// Path to create the IPC socket at
+    /// If not provided, a socket is created in $XDG_RUNTIME_DIR/r3
+    #[clap(long = "socket", short = 's')]
+    pub socket: Option<PathBuf>,
+
+    /// How much to log, overriding the configuration
+    #[clap(long = "log-level", arg_enum)]
+    pub log_level: Option<LogLevel>,
+
+    /// Replace the running window manager, if there is one
+    #[clap(long = "replace")]
+    pub replace: bool,
+
+    /// Check the configuration file for errors and exit, without connecting to X
+    #[clap(long = "check-config")]
+    pub check_config: bool,
+
+    /// Restore the state saved by a restart from this file (used by `R3Command::Restart`)
+    #[clap(long = "restore", hide = true)]
+    pub restore: Option<PathBuf>,
+}
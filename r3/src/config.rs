@@ -1,11 +1,298 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::{env, fmt, fs, io};
+
+use r3lib::{LogLevel, R3Command, WMCommand};
+use serde::{Deserialize, Serialize};
+use xcb::x;
+
+use crate::logging;
+use crate::rules::WindowRule;
+use crate::theme::Theme;
+
+/// A button drawn in a frame's title bar
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TitleBarButton {
+    /// Close the window
+    Close,
+    /// Toggle the window between maximized and its previous size
+    Maximize,
+    /// Toggle whether the window is floating
+    Float,
+}
+
+/// Which side of the title bar the buttons are placed on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TitleBarButtonSide {
+    Left,
+    Right,
+}
+
+/// How windows are focused with the pointer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FocusModel {
+    /// Windows are only focused when clicked
+    Click,
+    /// Focus follows the pointer into windows, but stays on the last window when the pointer is over the background
+    Sloppy,
+    /// Focus always follows the pointer: nothing is focused when it's over the background
+    StrictMouse,
+}
+
+/// A modifier key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Modifier {
+    Shift,
+    Control,
+    /// Usually Alt (Mod1)
+    Alt,
+    /// Usually the Windows key (Mod4)
+    Super,
+}
+
+impl Modifier {
+    pub fn key_but_mask(&self) -> x::KeyButMask {
+        match self {
+            Modifier::Shift => x::KeyButMask::SHIFT,
+            Modifier::Control => x::KeyButMask::CONTROL,
+            Modifier::Alt => x::KeyButMask::MOD1,
+            Modifier::Super => x::KeyButMask::MOD4,
+        }
+    }
+
+    pub fn mod_mask(&self) -> x::ModMask {
+        match self {
+            Modifier::Shift => x::ModMask::SHIFT,
+            Modifier::Control => x::ModMask::CONTROL,
+            Modifier::Alt => x::ModMask::N1,
+            Modifier::Super => x::ModMask::N4,
+        }
+    }
+
+    /// Index of the modifier in X's modifier mapping (Shift, Lock, Control, Mod1, ..., Mod5)
+    pub fn index(&self) -> usize {
+        match self {
+            Modifier::Shift => 0,
+            Modifier::Control => 2,
+            Modifier::Alt => 3,
+            Modifier::Super => 6,
+        }
+    }
+}
+
+/// Where a mouse binding applies
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MouseTarget {
+    /// Inside a client window
+    Window,
+    /// On a frame's border
+    Frame,
+    /// On a frame's title bar (but not its buttons)
+    TitleBar,
+    /// On the background
+    Root,
+}
+
+/// What a mouse binding does
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MouseAction {
+    /// Drag to move the window
+    Move,
+    /// Drag to resize the window from the nearest corner
+    Resize,
+    /// Raise the window above others
+    Raise,
+    /// Close the window
+    Close,
+    /// Focus the window
+    Focus,
+    /// Run a command, e.g. `{ command = { WM = "FocusUrgent" } }`
+    Command(R3Command),
+}
+
+/// A mouse button pressed with some modifiers on a target, e.g. Super + Button1 on a window
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MouseBinding {
+    /// Modifiers which must be held (others may be held too)
+    #[serde(default)]
+    pub modifiers: Vec<Modifier>,
+    /// The X button number: 1 is left, 2 middle, 3 right, and 4 and 5 scroll up and down
+    pub button: u8,
+    pub target: MouseTarget,
+    pub action: MouseAction,
+}
+
+impl MouseBinding {
+    fn new(modifiers: &[Modifier], button: u8, target: MouseTarget, action: MouseAction) -> MouseBinding {
+        MouseBinding {
+            modifiers: modifiers.to_vec(),
+            button,
+            target,
+            action,
+        }
+    }
+
+    pub fn matches(&self, button: u8, state: x::KeyButMask, target: MouseTarget) -> bool {
+        self.button == button
+            && self.target == target
+            && self.modifiers.iter().all(|m| state.contains(m.key_but_mask()))
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The configuration file couldn't be read
+    Io(PathBuf, io::Error),
+    /// The configuration file isn't valid
+    Parse(PathBuf, toml::de::Error),
+    /// The selected theme isn't defined
+    UnknownTheme(String),
+    /// A level was given for a log target which isn't one of r3's subsystems
+    UnknownLogTarget(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "failed to read {}: {}", path.display(), e),
+            ConfigError::Parse(path, e) => write!(f, "failed to parse {}: {}", path.display(), e),
+            ConfigError::UnknownTheme(name) => write!(f, "unknown theme: {:?}", name),
+            ConfigError::UnknownLogTarget(name) => {
+                write!(f, "unknown log target {:?} (expected x, ipc, focus or layout)", name)
+            }
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
-    pub focus_follows_mouse: bool,
+    /// How windows are focused with the pointer
+    pub focus_model: FocusModel,
+    /// Hold this modifier to move and resize windows by dragging anywhere inside them (with the default mouse
+    /// bindings)
+    pub modifier: Modifier,
+    /// Hold this modifier and press Tab to cycle through recently focused windows
+    pub cycle_modifier: Modifier,
+    /// Which buttons appear in the title bar, in order from left to right
+    pub title_bar_buttons: Vec<TitleBarButton>,
+    /// Which side of the title bar the buttons are placed on
+    pub title_bar_button_side: TitleBarButtonSide,
+    /// Name of the theme to use, either "default" or one defined in `themes`
+    pub theme: String,
+    /// User-defined themes, by name
+    pub themes: HashMap<String, Theme>,
+    /// Rules applied to windows when they're first mapped
+    pub rules: Vec<WindowRule>,
+    /// What mouse buttons do. The first binding that matches a click is used, and if this isn't set, then
+    /// `default_mouse_bindings` are used.
+    pub mouse_bindings: Option<Vec<MouseBinding>>,
+    /// Shell commands run when r3 starts
+    pub exec: Vec<String>,
+    /// Shell commands run when r3 starts, and again each time the configuration is reloaded
+    pub exec_always: Vec<String>,
+    /// How much r3 logs
+    pub log_level: LogLevel,
+    /// How much r3 logs for particular subsystems (x, ipc, focus or layout), overriding `log_level`
+    pub log_levels: HashMap<String, LogLevel>,
+    /// Whether to also log to a file in r3's runtime directory
+    pub log_file: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config::new()
+    }
 }
 
 impl Config {
     pub fn new() -> Config {
         Config {
-            focus_follows_mouse: true,
+            focus_model: FocusModel::Sloppy,
+            modifier: Modifier::Control,
+            cycle_modifier: Modifier::Alt,
+            title_bar_buttons: vec![TitleBarButton::Float, TitleBarButton::Maximize, TitleBarButton::Close],
+            title_bar_button_side: TitleBarButtonSide::Right,
+            theme: "default".into(),
+            themes: HashMap::new(),
+            rules: vec![],
+            exec: vec![],
+            exec_always: vec![],
+            mouse_bindings: None,
+            log_level: LogLevel::Info,
+            log_levels: HashMap::new(),
+            log_file: false,
+        }
+    }
+
+    pub fn mouse_bindings(&self) -> Cow<'_, [MouseBinding]> {
+        match &self.mouse_bindings {
+            Some(bindings) => Cow::Borrowed(bindings),
+            None => Cow::Owned(self.default_mouse_bindings()),
+        }
+    }
+
+    /// Drag windows with Button1 to move them and Button3 to resize them, either by their frame or anywhere inside
+    /// them while holding `modifier`. Scrolling on the background switches workspaces.
+    fn default_mouse_bindings(&self) -> Vec<MouseBinding> {
+        let modifier = &[self.modifier];
+        let cycle_workspace = |forward| MouseAction::Command(R3Command::WM(WMCommand::CycleWorkspace { forward }));
+        vec![
+            MouseBinding::new(modifier, 1, MouseTarget::Window, MouseAction::Move),
+            MouseBinding::new(modifier, 3, MouseTarget::Window, MouseAction::Resize),
+            MouseBinding::new(&[], 1, MouseTarget::TitleBar, MouseAction::Move),
+            MouseBinding::new(&[], 3, MouseTarget::TitleBar, MouseAction::Resize),
+            MouseBinding::new(&[], 1, MouseTarget::Frame, MouseAction::Move),
+            MouseBinding::new(&[], 3, MouseTarget::Frame, MouseAction::Resize),
+            MouseBinding::new(&[], 4, MouseTarget::Root, cycle_workspace(false)),
+            MouseBinding::new(&[], 5, MouseTarget::Root, cycle_workspace(true)),
+        ]
+    }
+
+    /// Default location of the configuration file: `$XDG_CONFIG_HOME/r3/config.toml`
+    pub fn default_path() -> PathBuf {
+        let dir = match env::var("XDG_CONFIG_HOME") {
+            Ok(dir) => PathBuf::from(dir),
+            Err(_) => PathBuf::from(env::var("HOME").unwrap_or_default()).join(".config"),
+        };
+
+        dir.join("r3").join("config.toml")
+    }
+
+    /// Read the configuration file at `path`. If it doesn't exist, the default configuration is returned.
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Config::new()),
+            Err(e) => return Err(ConfigError::Io(path.to_path_buf(), e)),
+        };
+
+        let config: Config = toml::from_str(&contents).map_err(|e| ConfigError::Parse(path.to_path_buf(), e))?;
+        config.get_theme(&config.theme)?;
+        if let Some(target) = config.log_levels.keys().find(|t| !logging::is_target(t)) {
+            return Err(ConfigError::UnknownLogTarget(target.clone()));
+        }
+
+        Ok(config)
+    }
+
+    /// Look up a theme by name: "default" is always available, unless the user has defined their own.
+    pub fn get_theme(&self, name: &str) -> Result<Theme, ConfigError> {
+        match (self.themes.get(name), name) {
+            (Some(theme), _) => Ok(theme.clone()),
+            (None, "default") => Ok(Theme::default()),
+            (None, _) => Err(ConfigError::UnknownTheme(name.into())),
         }
     }
 }
@@ -0,0 +1,83 @@
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+
+use nix::errno::Errno;
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::unistd::Pid;
+
+/// Processes started by r3, from the configuration's `exec` and `exec_always` lists or `R3Command::Exec`
+pub struct Children {
+    /// Passed to each process as `R3_SOCKET_PATH`, so it can control r3 with `r3-msg`
+    socket_path: PathBuf,
+    /// Each running process, and the command it was started with
+    running: Vec<(String, Child)>,
+}
+
+impl Children {
+    pub fn new(socket_path: &Path) -> Children {
+        Children {
+            socket_path: socket_path.to_path_buf(),
+            running: vec![],
+        }
+    }
+
+    /// Run a shell command in the background. It's started in its own session, so it isn't sent signals meant
+    /// for r3 (such as Ctrl+C, or the terminal r3 was started from closing).
+    pub fn spawn(&mut self, cmd: &str) {
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg(cmd)
+            .env("R3_SOCKET_PATH", &self.socket_path)
+            .stdin(Stdio::null());
+        // SAFETY: `setsid` is async-signal-safe, so can be called between fork and exec
+        unsafe {
+            command.pre_exec(|| nix::unistd::setsid().map(|_| ()).map_err(io::Error::from));
+        }
+
+        // Normally this is inherited from r3, but make sure it's set for the display we're managing
+        if let Ok(display) = std::env::var("DISPLAY") {
+            command.env("DISPLAY", display);
+        }
+
+        match command.spawn() {
+            Ok(child) => self.running.push((cmd.to_string(), child)),
+            Err(e) => log::error!("Failed to run {:?}: {}", cmd, e),
+        }
+    }
+
+    /// Wait on any processes which have exited (so they don't linger as zombies), logging those which failed.
+    /// This should be called whenever SIGCHLD is received. Every exited child is reaped, not only those in
+    /// `running`, since processes started before an in-place restart are still our children afterwards.
+    pub fn reap(&mut self) {
+        loop {
+            let (pid, failure) = match waitpid(Pid::from_raw(-1), Some(WaitPidFlag::WNOHANG)) {
+                Ok(WaitStatus::Exited(pid, 0)) => (pid, None),
+                Ok(WaitStatus::Exited(pid, code)) => (pid, Some(format!("exit status: {}", code))),
+                Ok(WaitStatus::Signaled(pid, signal, _)) => (pid, Some(format!("signal: {}", signal))),
+                // Stopped and continued children are still running
+                Ok(WaitStatus::StillAlive) | Err(Errno::ECHILD) => break,
+                Ok(_) => continue,
+                Err(e) => {
+                    log::error!("Failed to wait for child processes: {}", e);
+                    break;
+                }
+            };
+
+            // The command is only known if this process started it
+            let index = self
+                .running
+                .iter()
+                .position(|(_, child)| child.id() == pid.as_raw() as u32);
+            let cmd = index.map(|i| self.running.remove(i).0);
+            if let Some(failure) = failure {
+                match cmd {
+                    Some(cmd) => log::warn!("Command {:?} exited with {}", cmd, failure),
+                    None => log::warn!("Process {} exited with {}", pid, failure),
+                }
+            }
+        }
+    }
+}
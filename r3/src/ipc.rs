@@ -0,0 +1,240 @@
+use std::io::{self, Read, Write};
+use std::net::Shutdown;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use mio::Waker;
+use r3lib::{IpcError, IpcErrorKind, IpcErrorReply, OkReply, R3Command, WMEvent};
+use serde::Serialize;
+
+use crate::{logging, CommandQueue};
+
+/// Messages larger than this are rejected
+const MAX_MESSAGE_SIZE: u64 = 64 * 1024;
+/// Connections beyond this many are rejected until others have finished
+const MAX_CONNECTIONS: usize = 32;
+/// Subscriptions beyond this many are rejected. They stay open, so are counted separately from other connections.
+const MAX_SUBSCRIBERS: usize = 32;
+/// Connections are dropped if a message (or its reply) isn't sent within this time
+const TIMEOUT: Duration = Duration::from_secs(180);
+
+/// Channels to the connections which are subscribed to events, each of which is sent events as lines of JSON
+type Subscribers = Arc<Mutex<Vec<Sender<String>>>>;
+
+/// Accepts IPC connections, handling each in its own thread
+pub struct IpcServer {
+    listener: UnixListener,
+    waker: Arc<Waker>,
+    queue: CommandQueue,
+    /// The number of connections currently being handled (not including subscribed connections)
+    connections: Arc<AtomicUsize>,
+    subscribers: Subscribers,
+}
+
+/// Decrements the connection count when a connection's thread finishes (even if it panics), or it subscribes
+struct ConnectionGuard(Arc<AtomicUsize>);
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl IpcServer {
+    pub fn new(listener: UnixListener, waker: Arc<Waker>, queue: CommandQueue) -> IpcServer {
+        IpcServer {
+            listener,
+            waker,
+            queue,
+            connections: Arc::new(AtomicUsize::new(0)),
+            subscribers: Arc::new(Mutex::new(vec![])),
+        }
+    }
+
+    /// Send an event to every subscribed connection, forgetting those which have closed
+    pub fn publish(&self, event: &WMEvent) {
+        log::debug!(target: logging::IPC, "Event: {:?}", event);
+        let line = json_reply(event);
+        let mut subscribers = self.subscribers.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        subscribers.retain(|subscriber| subscriber.send(line.clone()).is_ok());
+    }
+
+    /// Accept all waiting connections, without blocking
+    pub fn accept_all(&self) {
+        loop {
+            match self.listener.accept() {
+                Ok((socket, addr)) => {
+                    log::debug!(target: logging::IPC, "Client connection: {:?} - {:?}", socket, addr);
+                    self.accept(socket);
+                }
+                // We tried to accept, but there are no more connections (we'd start blocking)
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                // Some other error occurred when accepting connections, try again next time
+                Err(e) => {
+                    log::error!(target: logging::IPC, "Failed to accept IPC connection: {}", e);
+                    break;
+                }
+            }
+        }
+    }
+
+    fn accept(&self, mut socket: UnixStream) {
+        if self.connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+            self.connections.fetch_sub(1, Ordering::SeqCst);
+            log::warn!(target: logging::IPC, "Too many IPC connections, rejecting");
+            let error = IpcError::new(IpcErrorKind::TooManyConnections, "too many connections");
+            // The client may not be listening, and there's nothing to do if it isn't
+            let _ = socket.set_nonblocking(true);
+            let _ = socket.write_all(error_reply(error).as_bytes());
+            let _ = socket.shutdown(Shutdown::Both);
+            return;
+        }
+
+        let guard = ConnectionGuard(self.connections.clone());
+        let waker = self.waker.clone();
+        let queue = self.queue.clone();
+        let subscribers = self.subscribers.clone();
+        let spawned = thread::Builder::new().name("ipc-client".into()).spawn(move || {
+            if let Err(e) = handle_client(&mut socket, &waker, &queue, &subscribers, guard) {
+                log::warn!(target: logging::IPC, "IPC connection failed: {}", e);
+            }
+            let _ = socket.shutdown(Shutdown::Both);
+            log::trace!(target: logging::IPC, "ipc client thread exit");
+        });
+
+        // The thread (and so the guard) is dropped if it couldn't be spawned
+        if let Err(e) = spawned {
+            log::error!(target: logging::IPC, "Failed to spawn IPC thread: {}", e);
+        }
+    }
+}
+
+/// Read a single command from the client, run it, and reply. Errors with the command itself are sent back to the
+/// client, and only errors with the connection are returned.
+fn handle_client(
+    socket: &mut UnixStream,
+    waker: &Waker,
+    queue: &CommandQueue,
+    subscribers: &Subscribers,
+    guard: ConnectionGuard,
+) -> io::Result<()> {
+    // The listener is non-blocking, but connections are handled synchronously in their own thread
+    socket.set_nonblocking(false)?;
+    socket.set_read_timeout(Some(TIMEOUT))?;
+    socket.set_write_timeout(Some(TIMEOUT))?;
+
+    // NOTE: the fastest way to deserialise right now is to read the entire body at once
+    // into a string and then deserialise that. See: https://github.com/serde-rs/json/issues/160
+    // Read one more byte than allowed, so we can tell if the message is too large
+    let mut message = vec![];
+    match socket.take(MAX_MESSAGE_SIZE + 1).read_to_end(&mut message) {
+        Ok(_) => {}
+        // The read took to long, so drop it
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+            log::warn!(target: logging::IPC, "IPC message took too long to read, dropping");
+            return Ok(());
+        }
+        Err(e) => return Err(e),
+    }
+
+    let reply = match parse_command(&message) {
+        Ok(R3Command::Subscribe) => return subscribe(socket, subscribers, guard),
+        Ok(command) => {
+            log::debug!(target: logging::IPC, "Client command: {:?}", command);
+            run_command(command, waker, queue)
+        }
+        Err(error) => {
+            log::warn!(target: logging::IPC, "Invalid IPC message: {}", error.message);
+            error_reply(error)
+        }
+    };
+
+    socket.write_all(reply.as_bytes())
+}
+
+/// Send events to the client until it goes away. Once it's been sent the first line (an `OkReply`), it won't miss
+/// any events.
+fn subscribe(socket: &mut UnixStream, subscribers: &Subscribers, guard: ConnectionGuard) -> io::Result<()> {
+    let (event_tx, event_rx) = mpsc::channel();
+    let mut subscribed = subscribers.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    // Closed subscriptions are only forgotten when the next event is published, but until then their threads are
+    // still waiting for it, so this limits the number of threads too
+    if subscribed.len() >= MAX_SUBSCRIBERS {
+        drop(subscribed);
+        log::warn!(target: logging::IPC, "Too many IPC subscriptions, rejecting");
+        let error = IpcError::new(IpcErrorKind::TooManyConnections, "too many subscriptions");
+        return socket.write_all(error_reply(error).as_bytes());
+    }
+    subscribed.push(event_tx);
+    drop(subscribed);
+
+    writeln!(socket, "{}", ok_reply())?;
+    // The subscription lasts as long as the client wants, so it no longer takes up one of the connection slots
+    drop(guard);
+    // This ends when the server stops, or the client closes the connection (which is noticed by the next write)
+    for event in event_rx {
+        writeln!(socket, "{}", event)?;
+    }
+
+    Ok(())
+}
+
+fn parse_command(message: &[u8]) -> Result<R3Command, IpcError> {
+    if message.len() as u64 > MAX_MESSAGE_SIZE {
+        return Err(IpcError::new(
+            IpcErrorKind::TooLarge,
+            format!("messages must be at most {} bytes", MAX_MESSAGE_SIZE),
+        ));
+    }
+
+    let message = std::str::from_utf8(message).map_err(|e| IpcError::new(IpcErrorKind::Parse, e.to_string()))?;
+    serde_json::from_str(message).map_err(|e| IpcError {
+        line: Some(e.line()),
+        column: Some(e.column()),
+        ..IpcError::new(IpcErrorKind::Parse, e.to_string())
+    })
+}
+
+/// Queue a command to run on the main thread, and wait for its reply
+fn run_command(command: R3Command, waker: &Waker, queue: &CommandQueue) -> String {
+    let (reply_tx, reply_rx) = mpsc::channel();
+
+    // If another thread panicked while holding the lock, the queue itself is still usable
+    let mut commands = queue.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    commands.push((command, Some(reply_tx)));
+    drop(commands);
+    if let Err(e) = waker.wake() {
+        return error_reply(IpcError::new(
+            IpcErrorKind::Internal,
+            format!("failed to run command: {}", e),
+        ));
+    }
+
+    // Queries send back a JSON reply, other commands are dropped without one
+    reply_rx.recv().unwrap_or_else(|_| ok_reply())
+}
+
+fn ok_reply() -> String {
+    // This can't fail, since `OkReply` only contains a bool
+    serde_json::to_string(&OkReply { ok: true }).unwrap_or_default()
+}
+
+/// Serialise the reply to a query, or describe why it couldn't be
+pub fn json_reply<T: Serialize>(value: &T) -> String {
+    serde_json::to_string(value).unwrap_or_else(|e| {
+        log::error!(target: logging::IPC, "Failed to serialise reply: {}", e);
+        error_reply(IpcError::new(
+            IpcErrorKind::Internal,
+            format!("failed to serialise reply: {}", e),
+        ))
+    })
+}
+
+pub fn error_reply(error: IpcError) -> String {
+    // This can't fail, since `IpcError` only contains strings and numbers
+    serde_json::to_string(&IpcErrorReply { error }).unwrap_or_default()
+}
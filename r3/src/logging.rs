@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Mutex, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::{LevelFilter, Log, Metadata, Record};
+use r3lib::LogLevel;
+
+/// Log targets for r3's subsystems, which can each be logged at their own level
+pub const X: &str = "x";
+pub const IPC: &str = "ipc";
+pub const FOCUS: &str = "focus";
+pub const LAYOUT: &str = "layout";
+const TARGETS: [&str; 4] = [X, IPC, FOCUS, LAYOUT];
+
+lazy_static::lazy_static! {
+    static ref LOGGER: Logger = Logger {
+        levels: RwLock::new(Levels {
+            default: LevelFilter::Info,
+            targets: HashMap::new(),
+        }),
+        file: Mutex::new(None),
+    };
+}
+
+struct Levels {
+    /// The level of targets which haven't been given their own
+    default: LevelFilter,
+    targets: HashMap<String, LevelFilter>,
+}
+
+impl Levels {
+    fn get(&self, target: &str) -> LevelFilter {
+        self.targets.get(target).copied().unwrap_or(self.default)
+    }
+
+    /// The most verbose level of any target, so the `log` macros can skip messages no target wants
+    fn max(&self) -> LevelFilter {
+        self.targets.values().copied().fold(self.default, Ord::max)
+    }
+}
+
+/// Writes log messages to stderr, and optionally to a file
+struct Logger {
+    levels: RwLock<Levels>,
+    file: Mutex<Option<File>>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        // If another thread panicked while holding the lock, the levels themselves are still usable
+        let levels = self.levels.read().unwrap_or_else(|e| e.into_inner());
+        metadata.level() <= levels.get(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let line = format!(
+            "{}.{:03} {:5} {}: {}\n",
+            time.as_secs(),
+            time.subsec_millis(),
+            record.level(),
+            record.target(),
+            record.args()
+        );
+
+        // There's nowhere to report a failure to log
+        let _ = std::io::stderr().write_all(line.as_bytes());
+        if let Some(file) = self.file.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+            let _ = file.write_all(line.as_bytes());
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = self.file.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+            let _ = file.flush();
+        }
+    }
+}
+
+fn level_filter(level: LogLevel) -> LevelFilter {
+    match level {
+        LogLevel::Off => LevelFilter::Off,
+        LogLevel::Error => LevelFilter::Error,
+        LogLevel::Warn => LevelFilter::Warn,
+        LogLevel::Info => LevelFilter::Info,
+        LogLevel::Debug => LevelFilter::Debug,
+        LogLevel::Trace => LevelFilter::Trace,
+    }
+}
+
+/// Start logging to stderr, at the info level. This should be called as early as possible.
+pub fn init() {
+    if log::set_logger(&*LOGGER).is_ok() {
+        log::set_max_level(LevelFilter::Info);
+    }
+}
+
+/// Whether `target` is one of r3's subsystems, so can be given its own level
+pub fn is_target(target: &str) -> bool {
+    TARGETS.contains(&target)
+}
+
+/// Change the level of one target, or of every target which hasn't been given its own
+pub fn set_level(level: LogLevel, target: Option<&str>) {
+    let mut levels = LOGGER.levels.write().unwrap_or_else(|e| e.into_inner());
+    match target {
+        Some(target) => {
+            levels.targets.insert(target.to_string(), level_filter(level));
+        }
+        None => levels.default = level_filter(level),
+    }
+    log::set_max_level(levels.max());
+}
+
+/// Replace all levels, e.g. with those from the configuration
+pub fn set_levels(default: LogLevel, targets: &HashMap<String, LogLevel>) {
+    let mut levels = LOGGER.levels.write().unwrap_or_else(|e| e.into_inner());
+    levels.default = level_filter(default);
+    levels.targets = targets.iter().map(|(t, l)| (t.clone(), level_filter(*l))).collect();
+    log::set_max_level(levels.max());
+}
+
+/// Also write log messages to a file (appending to it, since r3 keeps its pid across restarts), or stop if `path`
+/// is `None`
+pub fn set_file(path: Option<&Path>) {
+    let file = path.and_then(|path| match OpenOptions::new().create(true).append(true).open(path) {
+        Ok(file) => Some(file),
+        Err(e) => {
+            log::error!("Failed to open log file {}: {}", path.display(), e);
+            None
+        }
+    });
+
+    *LOGGER.file.lock().unwrap_or_else(|e| e.into_inner()) = file;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn levels(default: LevelFilter, targets: &[(&str, LevelFilter)]) -> Levels {
+        Levels {
+            default,
+            targets: targets.iter().map(|(t, l)| (t.to_string(), *l)).collect(),
+        }
+    }
+
+    #[test]
+    fn targets_override_default_level() {
+        let levels = levels(LevelFilter::Info, &[(X, LevelFilter::Trace), (IPC, LevelFilter::Off)]);
+        assert_eq!(LevelFilter::Trace, levels.get(X));
+        assert_eq!(LevelFilter::Off, levels.get(IPC));
+        assert_eq!(LevelFilter::Info, levels.get(FOCUS));
+        assert_eq!(LevelFilter::Info, levels.get("r3::wm"));
+    }
+
+    #[test]
+    fn max_level_is_most_verbose() {
+        assert_eq!(LevelFilter::Warn, levels(LevelFilter::Warn, &[]).max());
+        assert_eq!(
+            LevelFilter::Warn,
+            levels(LevelFilter::Warn, &[(X, LevelFilter::Error)]).max()
+        );
+        assert_eq!(
+            LevelFilter::Debug,
+            levels(
+                LevelFilter::Off,
+                &[(X, LevelFilter::Error), (LAYOUT, LevelFilter::Debug)]
+            )
+            .max()
+        );
+    }
+
+    #[test]
+    fn only_subsystems_are_targets() {
+        assert!(TARGETS.iter().all(|t| is_target(t)));
+        assert!(!is_target("xcb"));
+        assert!(!is_target(""));
+    }
+}
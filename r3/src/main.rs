@@ -1,38 +1,53 @@
+mod args;
 mod config;
+mod exec;
+mod ipc;
+mod logging;
 mod macros;
 mod point;
+mod restart;
+mod rules;
+mod theme;
 mod window_geometry;
 mod wm;
 
 use std::error::Error;
-use std::io::{self, Read, Write};
-use std::net::Shutdown;
 use std::os::unix::net::UnixListener;
 use std::os::unix::prelude::AsRawFd;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
-use std::{env, fs, process, thread};
+use std::{env, fs, process};
 
+use clap::Parser;
 use mio::unix::SourceFd;
 use mio::{Events, Interest, Poll, Token, Waker};
-use r3lib::R3Command;
-use signal_hook::consts::SIGTERM;
+use r3lib::{IpcError, IpcErrorKind, R3Command, VersionInfo};
+use signal_hook::consts::{SIGCHLD, SIGTERM};
 use signal_hook_mio::v0_8::Signals;
 use wm::WindowManager;
-use xcb::Connection;
+use xcb::{x, Connection};
+
+use crate::args::Args;
+use crate::config::Config;
+use crate::exec::Children;
+use crate::ipc::IpcServer;
 
 const T_XCB: Token = Token(0);
 const T_IPC: Token = Token(1);
 const T_CMD: Token = Token(2);
 const T_SIG: Token = Token(3);
 
-fn get_socket_path() -> Result<PathBuf, Box<dyn Error>> {
+/// Commands waiting to be run on the main thread, each with a channel to send its reply on (if it has one)
+pub type CommandQueue = Arc<Mutex<Vec<(R3Command, Option<Sender<String>>)>>>;
+
+/// The directory r3 keeps its runtime files (such as its IPC socket) in
+fn get_runtime_dir() -> Result<PathBuf, Box<dyn Error>> {
     let dir = match env::var("XDG_RUNTIME_DIR") {
         Ok(dir) => PathBuf::from(dir),
         Err(e) => {
-            eprintln!("Failed to interpret XDG_RUNTIME_DIR: {}", e);
-            eprintln!("Falling back to /tmp");
+            log::warn!("Failed to interpret XDG_RUNTIME_DIR, falling back to /tmp: {}", e);
             PathBuf::from("/tmp")
         }
     };
@@ -43,15 +58,83 @@ fn get_socket_path() -> Result<PathBuf, Box<dyn Error>> {
     // Create the directory
     fs::create_dir_all(&dir)?;
 
-    // Return the socket path
-    let socket_path = dir.join(format!("ipc-socket.{}", process::id()));
-    eprintln!("socket_path: {}", socket_path.display());
-    Ok(socket_path)
+    Ok(dir)
+}
+
+fn get_socket_path(runtime_dir: &Path) -> PathBuf {
+    let socket_path = runtime_dir.join(format!("ipc-socket.{}", process::id()));
+    log::info!(target: logging::IPC, "socket_path: {}", socket_path.display());
+    socket_path
+}
+
+/// Log at the configured levels (unless overridden on the command line), and to a file in the runtime directory if
+/// that's enabled
+fn configure_logging(config: &Config, args: &Args, runtime_dir: &Path) {
+    logging::set_levels(config.log_level, &config.log_levels);
+    if let Some(level) = args.log_level {
+        logging::set_level(level, None);
+    }
+    let log_path = runtime_dir.join(format!("r3.{}.log", process::id()));
+    logging::set_file(config.log_file.then_some(log_path.as_path()));
+}
+
+/// Tell the IPC client which sent a command (if there is one) that the command failed
+fn send_error(reply: &Option<Sender<String>>, kind: IpcErrorKind, message: String) {
+    if let Some(reply) = reply {
+        // The client may have gone away, in which case there's nobody to reply to
+        let _ = reply.send(ipc::error_reply(IpcError::new(kind, message)));
+    }
+}
+
+/// A configuration file passed with `--config` has to exist, while the default one is optional
+fn check_explicit_config(path: &Path, explicit: bool) -> Result<(), Box<dyn Error>> {
+    match explicit && !path.exists() {
+        true => Err(format!("{} doesn't exist", path.display()).into()),
+        false => Ok(()),
+    }
+}
+
+/// Load the configuration file and report any problems with it, returning an error if there are any
+fn check_config(path: &Path, explicit: bool) -> Result<(), Box<dyn Error>> {
+    check_explicit_config(path, explicit)?;
+    if !path.exists() {
+        println!(
+            "{} doesn't exist, so the default configuration would be used",
+            path.display()
+        );
+        return Ok(());
+    }
+
+    Config::load(path).map_err(|e| e.to_string())?;
+    println!("{} is valid", path.display());
+    Ok(())
 }
 
 // TODO: consider abstracting away X-specific items, and allowing Wayland impls too?
 //  unsure how difficult this will be (seems to be mostly X code for now)
 fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+    logging::init();
+
+    // Configuration:
+    //  Fall back to the default configuration if the user's can't be loaded, so there's still a usable WM
+    let config_path = args.config.clone().unwrap_or_else(Config::default_path);
+    if args.check_config {
+        return check_config(&config_path, args.config.is_some());
+    }
+    check_explicit_config(&config_path, args.config.is_some())?;
+    let config = Config::load(&config_path).unwrap_or_else(|e| {
+        log::error!("Failed to load configuration, using defaults: {}", e);
+        Config::new()
+    });
+    let runtime_dir = get_runtime_dir()?;
+    configure_logging(&config, &args, &runtime_dir);
+
+    // Commands we run should use the display we're managing
+    if let Some(display) = &args.display {
+        env::set_var("DISPLAY", display);
+    }
+
     // Stop and wait for debugger if R3_DEBUG present
     #[cfg(feature = "debug")]
     if matches!(std::env::var("R3_DEBUG"), Ok(_)) {
@@ -67,29 +150,59 @@ fn main() -> Result<(), Box<dyn Error>> {
     let mut events = Events::with_capacity(128);
     let registry = poll.registry();
     let loop_waker = Arc::new(Waker::new(registry, T_CMD)?);
-    let command_queue: Arc<Mutex<Vec<R3Command>>> = Arc::new(Mutex::new(vec![]));
+    let command_queue: CommandQueue = Arc::new(Mutex::new(vec![]));
 
     // IPC setup:
-    //  TODO: doc
-    let socket_path = get_socket_path()?;
+    //  Each connection is handled in its own thread, which queues its command for the event loop
+    let socket_path = args.socket.clone().unwrap_or_else(|| get_socket_path(&runtime_dir));
+    if args.restore.is_some() {
+        // The process we replaced when restarting left its socket behind
+        let _ = fs::remove_file(&socket_path);
+    }
     let ipc_socket = UnixListener::bind(&socket_path)?;
     ipc_socket.set_nonblocking(true)?;
     registry.register(&mut SourceFd(&ipc_socket.as_raw_fd()), T_IPC, Interest::READABLE)?;
+    let ipc_server = IpcServer::new(ipc_socket, loop_waker.clone(), command_queue.clone());
+
+    // Autostart:
+    //  These are run once we're the window manager, so windows they create are managed. After a restart, only
+    //  `exec_always` commands are run again.
+    let exec = match args.restore {
+        Some(_) => &[][..],
+        None => &config.exec[..],
+    };
+    let autostart = exec.iter().chain(&config.exec_always).cloned().collect::<Vec<_>>();
+    let mut children = Children::new(&socket_path);
 
     // XCB setup:
     //  If display name is none, rust-xcb will use the DISPLAY environment variable
     //  TODO: doc
-    let (xcb_conn, xcb_default_screen) = Connection::connect_with_extensions(None, &[], &[])?;
+    let (xcb_conn, xcb_default_screen) =
+        Connection::connect_with_extensions(args.display.as_deref(), &[], &[xcb::Extension::Sync])?;
     let mut wm = WindowManager::new(
         (&xcb_conn, xcb_default_screen),
         (loop_waker.clone(), command_queue.clone()),
+        config,
     )?;
-    wm.become_window_manager(&socket_path)?;
+    if let Err(e) = wm.become_window_manager(&socket_path, args.replace) {
+        drop(ipc_server);
+        fs::remove_file(&socket_path)?;
+        return Err(e.into());
+    }
+    if let Some(path) = &args.restore {
+        match restart::load_state(path) {
+            Ok(state) => wm.restore_state(state)?,
+            Err(e) => log::error!("Failed to restore state after restart: {}", e),
+        }
+    }
+    for cmd in &autostart {
+        children.spawn(cmd);
+    }
     registry.register(&mut SourceFd(&xcb_conn.as_raw_fd()), T_XCB, Interest::READABLE)?;
 
     // Signal setup:
     //  TODO doc
-    let mut signals = Signals::new(&[SIGTERM])?;
+    let mut signals = Signals::new([SIGTERM, SIGCHLD])?;
     registry.register(&mut signals, T_SIG, Interest::READABLE)?;
 
     // The event loop!
@@ -99,17 +212,29 @@ fn main() -> Result<(), Box<dyn Error>> {
         // to make sure that XCB's incoming and outgoing queues are completely empty, so there are
         // no race conditions between `poll`ing the file descriptor and data being ready there
         {
-            // Loop until we consume all available XCB events
+            // Loop until we consume all available XCB events. While dragging, the pointer moves much faster than
+            // windows can be moved or resized, so a run of MotionNotify events is compressed into its latest one.
+            let mut motion = None;
             loop {
                 match xcb_conn.poll_for_event() {
                     // No events left to read
                     Ok(None) => break,
+                    Ok(Some(xcb::Event::X(x::Event::MotionNotify(ev)))) => motion = Some(ev),
                     // We read an xcb event
-                    Ok(Some(ev)) => wm.handle_event(Ok(ev))?,
+                    Ok(Some(ev)) => {
+                        if let Some(motion) = motion.take() {
+                            wm.handle_event(Ok(xcb::Event::X(x::Event::MotionNotify(motion))))?;
+                        }
+                        wm.handle_event(Ok(ev))?;
+                    }
                     // Some error occurred when polling/reading event
                     Err(e) => wm.handle_event(Err(e))?,
                 }
             }
+            if let Some(motion) = motion {
+                wm.handle_event(Ok(xcb::Event::X(x::Event::MotionNotify(motion))))?;
+            }
+            wm.apply_drag_resize()?;
             xcb_conn.flush()?;
         }
 
@@ -120,63 +245,106 @@ fn main() -> Result<(), Box<dyn Error>> {
                 T_XCB => {
                     // We do nothing here, since we process all XCB events before blocking the event loop
                 }
-                T_IPC => {
-                    // Loop until we've accepted all waiting IPC connections
-                    loop {
-                        match ipc_socket.accept() {
-                            // We got an IPC connection, read it and send a message back
-                            Ok((mut socket, addr)) => {
-                                println!("Client connection: {:?} - {:?}", socket, addr);
-                                let thread_waker = loop_waker.clone();
-                                let thread_commands = command_queue.clone();
-                                thread::Builder::new().name(format!("ipc-client")).spawn(move || {
-                                    // Timeout connection after periods of inactivity
-                                    socket.set_read_timeout(Some(Duration::from_secs(180))).unwrap();
-
-                                    let mut message = String::new();
-
-                                    // NOTE: the fastest way to deserialise right now is to read the entire body at once
-                                    // into a string and then deserialise that. See: https://github.com/serde-rs/json/issues/160
-                                    match socket.read_to_string(&mut message) {
-                                        Ok(_) => {
-                                            println!("Client message: {}", message);
-                                            let command: R3Command = serde_json::from_str(&message).unwrap();
-                                            println!("Client command: {:?}", command);
-                                            thread_commands.lock().unwrap().push(command);
-                                            thread_waker.wake().unwrap();
-
-                                            // TODO: construct JSON reply
-                                            socket.write_all(b"Hello from the server!").unwrap();
-                                            socket.shutdown(Shutdown::Both).unwrap();
-                                        }
-                                        // The read took to long, so drop it
-                                        Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
-                                            eprintln!("IPC message took too long to read, dropping");
-                                            return;
-                                        }
-                                        // Some other error occurred when reading
-                                        Err(e) => panic!("ipc read error: {}", e),
-                                    }
-
-                                    println!("ipc client thread exit");
-                                })?;
-                            }
-                            // We tried to accept, but there are no more connections (we'd start blocking)
-                            Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
-                            // Some other error occurred when accepting/reading connections
-                            Err(e) => panic!("ipc error: {}", e),
-                        }
-                    }
-                }
+                T_IPC => ipc_server.accept_all(),
                 T_CMD => {
-                    let cmds = command_queue.lock().unwrap().drain(..).collect::<Vec<_>>();
-                    for cmd in cmds {
+                    let cmds = command_queue
+                        .lock()
+                        .unwrap_or_else(|poisoned| poisoned.into_inner())
+                        .drain(..)
+                        .collect::<Vec<_>>();
+                    for (cmd, reply) in cmds {
                         // TODO: extend R3Command so there are WM-specific commands and app-specific commands
                         match cmd {
                             R3Command::WM(wm_cmd) => wm.handle_command(&wm_cmd)?,
-                            // TODO: how to send reply? wrap items in command_queue with a context/reply/etc field/closure?
-                            R3Command::GetConfig => todo!(),
-                            R3Command::GetVersion => todo!(),
+                            // The client may have gone away, in which case there's nobody to reply to
+                            R3Command::GetConfig => {
+                                if let Some(reply) = reply {
+                                    let _ = reply.send(ipc::json_reply(wm.config()));
+                                }
+                            }
+                            R3Command::GetVersion => {
+                                if let Some(reply) = reply {
+                                    let version = VersionInfo {
+                                        version: env!("CARGO_PKG_VERSION").into(),
+                                    };
+                                    let _ = reply.send(ipc::json_reply(&version));
+                                }
+                            }
+                            R3Command::GetWindows => {
+                                if let Some(reply) = reply {
+                                    let _ = reply.send(ipc::json_reply(&wm.get_windows()));
+                                }
+                            }
+                            // Subscriptions are handled by the IPC server, which sends the events we publish
+                            R3Command::Subscribe => {}
+                            R3Command::Exec { cmd } => children.spawn(&cmd),
+                            R3Command::Reload => match Config::load(&config_path) {
+                                Ok(config) => {
+                                    configure_logging(&config, &args, &runtime_dir);
+                                    for cmd in &config.exec_always {
+                                        children.spawn(cmd);
+                                    }
+                                    wm.reload_config(config)?;
+                                }
+                                Err(e) => {
+                                    log::error!("Failed to reload configuration: {}", e);
+                                    send_error(&reply, IpcErrorKind::Invalid, e.to_string());
+                                }
+                            },
+                            R3Command::Restart => {
+                                // If there's nothing to restart with, or the state can't be saved, keep running
+                                // rather than losing it
+                                let exe = match restart::executable() {
+                                    Ok(exe) => exe,
+                                    Err(e) => {
+                                        log::error!("Failed to find r3 to restart: {}", e);
+                                        send_error(&reply, IpcErrorKind::Internal, format!("failed to find r3: {}", e));
+                                        continue;
+                                    }
+                                };
+                                let state_path = restart::state_path(&runtime_dir);
+                                let state = match wm.save_state() {
+                                    Ok(state) => state,
+                                    Err(e) => {
+                                        log::error!("Failed to save state for restart: {}", e);
+                                        send_error(
+                                            &reply,
+                                            IpcErrorKind::Internal,
+                                            format!("failed to save state: {}", e),
+                                        );
+                                        continue;
+                                    }
+                                };
+                                if let Err(e) = restart::save_state(&state_path, &state) {
+                                    log::error!("Failed to save state for restart: {}", e);
+                                    send_error(&reply, IpcErrorKind::Internal, format!("failed to save state: {}", e));
+                                    continue;
+                                }
+
+                                // The new process takes over our IPC socket, since it keeps our pid
+                                log::info!("r3 restarting...");
+                                let e: Box<dyn Error> = match wm.release() {
+                                    Ok(()) => restart::exec(&exe, &state_path).into(),
+                                    Err(e) => e.into(),
+                                };
+
+                                // We're still here, so manage the windows again
+                                log::error!("Failed to restart: {}", e);
+                                send_error(&reply, IpcErrorKind::Internal, format!("failed to restart: {}", e));
+                                if let Err(e) = fs::remove_file(&state_path) {
+                                    log::warn!("Failed to remove {}: {}", state_path.display(), e);
+                                }
+                                wm.become_window_manager(&socket_path, false)?;
+                                wm.restore_state(state)?;
+                            }
+                            R3Command::SetLogLevel { level, target } => match target.as_deref() {
+                                Some(target) if !logging::is_target(target) => {
+                                    let message =
+                                        format!("unknown log target {:?} (expected x, ipc, focus or layout)", target);
+                                    send_error(&reply, IpcErrorKind::Invalid, message);
+                                }
+                                target => logging::set_level(level, target),
+                            },
                             R3Command::Exit => break 'event_loop,
                         }
                     }
@@ -185,9 +353,10 @@ fn main() -> Result<(), Box<dyn Error>> {
                     for sig in signals.pending() {
                         match sig {
                             SIGTERM => {
-                                eprintln!("Received: SIGTERM");
+                                log::info!("Received: SIGTERM");
                                 break 'event_loop;
                             }
+                            SIGCHLD => children.reap(),
                             _ => unimplemented!("{:?}", sig),
                         }
                     }
@@ -195,11 +364,15 @@ fn main() -> Result<(), Box<dyn Error>> {
                 _ => unreachable!(),
             }
         }
+
+        for event in wm.take_events() {
+            ipc_server.publish(&event);
+        }
     }
 
     // Clean up before exit
-    eprintln!("r3 exiting...");
-    drop(ipc_socket);
+    log::info!("r3 exiting...");
+    drop(ipc_server);
     fs::remove_file(&socket_path)?;
 
     Ok(())
@@ -0,0 +1,69 @@
+use std::error::Error;
+use std::ffi::OsString;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::{env, fs, io, process};
+
+use crate::wm::SavedState;
+
+/// Where the window manager's state is saved while restarting
+pub fn state_path(runtime_dir: &Path) -> PathBuf {
+    runtime_dir.join(format!("restart-state.{}.json", process::id()))
+}
+
+/// Save the window manager's state, to be restored by the new process
+pub fn save_state(path: &Path, state: &SavedState) -> Result<(), Box<dyn Error>> {
+    fs::write(path, serde_json::to_vec(state)?)?;
+    Ok(())
+}
+
+/// Read (and then remove) the state saved before a restart
+pub fn load_state(path: &Path) -> Result<SavedState, Box<dyn Error>> {
+    let state = fs::read(path);
+    if let Err(e) = fs::remove_file(path) {
+        log::warn!("Failed to remove {}: {}", path.display(), e);
+    }
+
+    Ok(serde_json::from_slice(&state?)?)
+}
+
+/// The r3 binary to restart with. This is checked before restarting, since there's no going back once windows
+/// have been released.
+pub fn executable() -> io::Result<PathBuf> {
+    // If r3 was rebuilt while running, Linux reports the (replaced) binary's path with " (deleted)" appended
+    let exe = env::current_exe()?;
+    let exe = match exe.to_str().and_then(|path| path.strip_suffix(" (deleted)")) {
+        Some(path) => PathBuf::from(path),
+        None => exe,
+    };
+
+    match exe.is_file() {
+        true => Ok(exe),
+        false => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} doesn't exist", exe.display()),
+        )),
+    }
+}
+
+/// Replace this process with a new r3 (from `exe`), started with the same arguments, which restores the state
+/// saved at `state_path`. This only returns if the new process couldn't be started.
+pub fn exec(exe: &Path, state_path: &Path) -> io::Error {
+    // Drop the state file of the restart that started us, if there was one
+    let mut args = vec![];
+    let mut original_args = env::args_os().skip(1);
+    while let Some(arg) = original_args.next() {
+        match arg.to_str() {
+            Some("--restore") => {
+                original_args.next();
+            }
+            Some(a) if a.starts_with("--restore=") => {}
+            _ => args.push(arg),
+        }
+    }
+    args.push(OsString::from("--restore"));
+    args.push(state_path.into());
+
+    Command::new(exe).args(args).exec()
+}
@@ -0,0 +1,67 @@
+use r3lib::R3Command;
+use serde::{Deserialize, Serialize};
+
+/// The properties of a window which rules are matched against
+#[derive(Debug, Default)]
+pub struct WindowProperties {
+    /// The class part of `WM_CLASS`, e.g. "Firefox"
+    pub class: String,
+    /// The instance part of `WM_CLASS`, e.g. "Navigator"
+    pub instance: String,
+    pub title: String,
+    /// `WM_WINDOW_ROLE`
+    pub role: String,
+    /// `_NET_WM_WINDOW_TYPE`, without the `_NET_WM_WINDOW_TYPE_` prefix and in lowercase, e.g. "dialog"
+    pub window_types: Vec<String>,
+}
+
+/// A rule applied to windows when they're first mapped. If every criteria that's set matches the window, all of
+/// the rule's actions are applied. When several rules match, they're applied in order.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowRule {
+    // Criteria
+    /// Matches the class part of `WM_CLASS` exactly
+    pub class: Option<String>,
+    /// Matches the instance part of `WM_CLASS` exactly
+    pub instance: Option<String>,
+    /// Matches if the window's title contains this text
+    pub title: Option<String>,
+    /// Matches `WM_WINDOW_ROLE` exactly
+    pub role: Option<String>,
+    /// Matches one of the window's types, e.g. "normal", "dialog" or "utility"
+    pub window_type: Option<String>,
+
+    // Actions
+    /// Put the window on this workspace (numbered from 0)
+    pub workspace: Option<usize>,
+    /// Make the window floating (or not)
+    pub floating: Option<bool>,
+    /// Move the window to this position, measured from inside the theme's gap
+    pub position: Option<(i16, i16)>,
+    /// Resize the window to this size (not including its frame)
+    pub size: Option<(u16, u16)>,
+    /// Don't focus the window when it's mapped
+    pub skip_focus: bool,
+    /// Don't draw a border around the window's frame
+    pub no_border: bool,
+    /// Show the window on every workspace
+    pub sticky: bool,
+    /// Run a command, e.g. `{ WM = { SwitchWorkspace = { index = 2 } } }`
+    pub command: Option<R3Command>,
+}
+
+impl WindowRule {
+    pub fn matches(&self, properties: &WindowProperties) -> bool {
+        let exact = |criteria: &Option<String>, value: &str| criteria.as_deref().is_none_or(|c| c == value);
+
+        exact(&self.class, &properties.class)
+            && exact(&self.instance, &properties.instance)
+            && exact(&self.role, &properties.role)
+            && self.title.as_deref().is_none_or(|t| properties.title.contains(t))
+            && self
+                .window_type
+                .as_ref()
+                .is_none_or(|t| properties.window_types.contains(t))
+    }
+}
@@ -0,0 +1,163 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// An RGB colour, stored as `0xRRGGBB` (which is also its pixel value on a 24-bit TrueColor visual)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color(pub u32);
+
+/// Named colours that can be used in place of `#rrggbb`
+const NAMED_COLORS: &[(&str, u32)] = &[
+    ("black", 0x000000),
+    ("white", 0xffffff),
+    ("red", 0xff0000),
+    ("green", 0x00ff00),
+    ("blue", 0x0000ff),
+    ("yellow", 0xffff00),
+    ("cyan", 0x00ffff),
+    ("magenta", 0xff00ff),
+    ("orange", 0xffa500),
+    ("purple", 0xa020f0),
+    ("pink", 0xffc0cb),
+    ("brown", 0xa52a2a),
+    ("gray", 0xbebebe),
+    ("grey", 0xbebebe),
+    ("darkgray", 0xa9a9a9),
+    ("darkgrey", 0xa9a9a9),
+    ("lightgray", 0xd3d3d3),
+    ("lightgrey", 0xd3d3d3),
+    ("navy", 0x000080),
+    ("darkblue", 0x00008b),
+    ("darkred", 0x8b0000),
+    ("darkgreen", 0x006400),
+    ("maroon", 0xb03060),
+    ("teal", 0x008080),
+    ("olive", 0x808000),
+    ("silver", 0xc0c0c0),
+];
+
+impl FromStr for Color {
+    type Err = String;
+
+    /// Parse a colour from `#rrggbb`, `#rgb` or a name (case and whitespace are ignored, so "Dark Gray" works)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(hex) = s.strip_prefix('#') {
+            // `from_str_radix` also accepts a leading sign, so check the digits first
+            if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(format!("invalid hex colour: {:?}", s));
+            }
+            let value = u32::from_str_radix(hex, 16).map_err(|_| format!("invalid hex colour: {:?}", s))?;
+            return match hex.len() {
+                6 => Ok(Color(value)),
+                // Expand #rgb to #rrggbb
+                3 => {
+                    let (r, g, b) = ((value >> 8) & 0xf, (value >> 4) & 0xf, value & 0xf);
+                    Ok(Color((r * 0x11) << 16 | (g * 0x11) << 8 | (b * 0x11)))
+                }
+                _ => Err(format!("hex colours must be #rrggbb or #rgb: {:?}", s)),
+            };
+        }
+
+        let name = s.replace(char::is_whitespace, "").to_lowercase();
+        NAMED_COLORS
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, value)| Color(*value))
+            .ok_or_else(|| format!("unknown colour: {:?}", s))
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for Color {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:06x}", self.0)
+    }
+}
+
+/// The colours used to draw a frame in a particular state
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ColorSet {
+    /// The frame's border
+    pub border: Color,
+    /// The title bar's background
+    pub background: Color,
+    /// The title bar's text and button icons
+    pub text: Color,
+    /// Title bar buttons
+    pub button: Color,
+    /// Title bar buttons when the pointer is over them
+    pub button_hover: Color,
+    /// Title bar buttons whose state is on (e.g., a maximized window's maximize button)
+    pub button_active: Color,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Theme {
+    /// Colours for the focused window
+    pub focused: ColorSet,
+    /// Colours for all other windows
+    pub unfocused: ColorSet,
+    /// Colours for windows which are demanding attention
+    pub urgent: ColorSet,
+    /// Width of the border around each frame
+    pub border_width: u16,
+    /// Height of the title bar drawn at the top of every frame
+    pub title_bar_height: u16,
+    /// X core font pattern used to draw frame titles (falls back to "fixed" if it can't be opened)
+    pub font: String,
+    /// Horizontal space between the edge of the title bar and its text
+    pub padding: u16,
+    /// Space left between the edges of the screen and maximized windows (or windows positioned by rules)
+    pub gap: u16,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            focused: ColorSet {
+                border: Color(0xff0000),
+                background: Color(0x0000ff),
+                text: Color(0xffffff),
+                button: Color(0x3333cc),
+                button_hover: Color(0x6666ff),
+                button_active: Color(0x000099),
+            },
+            unfocused: ColorSet {
+                border: Color(0xaaaaaa),
+                background: Color(0x0000ff),
+                text: Color(0xffffff),
+                button: Color(0x3333cc),
+                button_hover: Color(0x6666ff),
+                button_active: Color(0x000099),
+            },
+            urgent: ColorSet {
+                border: Color(0xffa500),
+                background: Color(0xcc6600),
+                text: Color(0xffffff),
+                button: Color(0xdd8833),
+                button_hover: Color(0xffaa55),
+                button_active: Color(0x994400),
+            },
+            border_width: 10,
+            title_bar_height: 20,
+            font: "-misc-fixed-medium-r-normal--13-*-*-*-*-*-iso8859-1".into(),
+            padding: 4,
+            gap: 0,
+        }
+    }
+}
@@ -1,3 +1,6 @@
+use r3lib::Direction;
+use serde::{Deserialize, Serialize};
+
 use crate::point::Point;
 
 #[derive(Debug, Clone, Copy)]
@@ -8,7 +11,7 @@ pub enum Quadrant {
     BottomRight,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct WindowGeometry {
     /// X coord relative to parent
     pub x: i16,
@@ -50,6 +53,46 @@ impl WindowGeometry {
         (self.x <= point.x && point.x <= end_x) && (self.y <= point.y && point.y <= end_y)
     }
 
+    /// The centre of the window (including border)
+    pub fn center(&self) -> Point {
+        Point::new(
+            self.x + (self.full_width() / 2) as i16,
+            self.y + (self.full_height() / 2) as i16,
+        )
+    }
+
+    /// How far away `other` is in the given direction, or `None` if its centre isn't in that direction.
+    /// Windows which overlap this one on the perpendicular axis are preferred over those which are off to the side.
+    pub fn distance_in_direction(&self, other: &WindowGeometry, direction: Direction) -> Option<u32> {
+        let (from, to) = (self.center(), other.center());
+        let (dx, dy) = (to.x as i32 - from.x as i32, to.y as i32 - from.y as i32);
+        let (along, across, overlaps) = match direction {
+            Direction::Left => (-dx, dy, self.overlaps_vertically(other)),
+            Direction::Right => (dx, dy, self.overlaps_vertically(other)),
+            Direction::Up => (-dy, dx, self.overlaps_horizontally(other)),
+            Direction::Down => (dy, dx, self.overlaps_horizontally(other)),
+        };
+
+        if along <= 0 {
+            return None;
+        }
+
+        let across = if overlaps { 0 } else { across.unsigned_abs() };
+        Some(along as u32 + across * 2)
+    }
+
+    /// Do the windows (including borders) share any columns?
+    fn overlaps_horizontally(&self, other: &WindowGeometry) -> bool {
+        let (x, other_x) = (self.x as i32, other.x as i32);
+        x < other_x + other.full_width() as i32 && other_x < x + self.full_width() as i32
+    }
+
+    /// Do the windows (including borders) share any rows?
+    fn overlaps_vertically(&self, other: &WindowGeometry) -> bool {
+        let (y, other_y) = (self.y as i32, other.y as i32);
+        y < other_y + other.full_height() as i32 && other_y < y + self.full_height() as i32
+    }
+
     pub fn quadrant(&self, point: &Point) -> Option<Quadrant> {
         let horizonal_bound = self.x + (self.full_width() / 2) as i16;
         let vertical_bound = self.y + (self.full_height() / 2) as i16;
@@ -0,0 +1,35 @@
+use xcb::x;
+
+use super::sync::SyncState;
+use crate::window_geometry::WindowGeometry;
+
+/// State r3 keeps about each client window it manages
+#[derive(Debug, Default)]
+pub struct Client {
+    /// The window's title, read from `_NET_WM_NAME` or `WM_NAME`
+    pub title: String,
+    /// The title bar button windows created for this client, in order from left to right
+    pub buttons: Vec<x::Window>,
+    /// If the window is maximized, this is the frame's geometry from before it was maximized
+    pub unmaximized_rect: Option<WindowGeometry>,
+    /// Floating windows are always stacked above other windows
+    pub floating: bool,
+    /// The workspace the window is on
+    pub workspace: usize,
+    /// Whether the window is hidden in the scratchpad, in which case it isn't shown on any workspace
+    pub scratchpad_hidden: bool,
+    /// Whether the window accepts input focus (`WM_HINTS.input`)
+    pub accepts_input: bool,
+    /// Whether the window supports the `WM_TAKE_FOCUS` protocol
+    pub takes_focus: bool,
+    /// Whether the window has the `_NET_WM_STATE_DEMANDS_ATTENTION` state, as of when it was last read
+    pub demands_attention: bool,
+    /// If the window supports `_NET_WM_SYNC_REQUEST`, the state used to wait for it to repaint when it's resized
+    pub sync: Option<SyncState>,
+    /// Whether the frame is drawn without a border
+    pub no_border: bool,
+    /// Sticky windows are shown on every workspace
+    pub sticky: bool,
+    /// Number of UnmapNotify events for the frame that were caused by us hiding it, and should be ignored
+    pub ignore_unmaps: u32,
+}
@@ -1,18 +1,79 @@
-use r3lib::WMCommand;
+use r3lib::{WMCommand, WindowInfo};
+use xcb::Xid;
 
+use super::workspaces::WORKSPACE_COUNT;
 use super::WindowManager;
+use crate::logging;
 
 impl<'a> WindowManager<'a> {
+    /// Run a command. Like `handle_event`, only an error with the X connection itself is returned.
     pub fn handle_command(&mut self, cmd: &WMCommand) -> xcb::Result<()> {
+        let result = self.run_command(cmd);
+        self.recover(result)?;
+
+        let result = self.render();
+        self.recover(result)
+    }
+
+    fn run_command(&mut self, cmd: &WMCommand) -> xcb::Result<()> {
         match cmd {
             WMCommand::CloseWindow => {
-                if let Some(window) = self.focused_window.take() {
-                    println!("focused: {:?}", window);
+                if let Some(window) = self.focused_window {
+                    log::debug!(target: logging::FOCUS, "Closing focused window: {:?}", window);
                     self.kill_window(window)?;
                 }
             }
+            WMCommand::ToggleMaximize => {
+                if let Some(window) = self.focused_window {
+                    self.toggle_maximize(window)?;
+                }
+            }
+            WMCommand::ToggleFloating => {
+                if let Some(window) = self.focused_window {
+                    self.toggle_floating(window)?;
+                }
+            }
+            WMCommand::SetTheme { name } => match self.config.get_theme(name) {
+                Ok(theme) => self.set_theme(theme)?,
+                Err(e) => log::error!("Failed to set theme: {}", e),
+            },
+            WMCommand::SwitchWorkspace { index } => self.switch_workspace(*index)?,
+            WMCommand::CycleWorkspace { forward } => {
+                let offset = if *forward { 1 } else { WORKSPACE_COUNT - 1 };
+                self.switch_workspace((self.current_workspace + offset) % WORKSPACE_COUNT)?;
+            }
+            WMCommand::MoveToWorkspace { index } => {
+                if let Some(window) = self.focused_window {
+                    self.move_to_workspace(window, *index)?;
+                }
+            }
+            WMCommand::MoveToScratchpad => {
+                if let Some(window) = self.focused_window {
+                    self.move_to_scratchpad(window)?;
+                }
+            }
+            WMCommand::ScratchpadShow { criteria } => self.scratchpad_show(criteria.as_deref())?,
+            WMCommand::CycleFocus { forward } => self.cycle_focus(*forward)?,
+            WMCommand::FocusDirection { direction } => self.focus_direction(*direction)?,
+            WMCommand::Swap { direction } => self.swap_direction(*direction)?,
+            WMCommand::FocusUrgent => self.focus_urgent()?,
         }
 
         Ok(())
     }
+
+    /// Describe every managed window, for IPC clients
+    pub fn get_windows(&self) -> Vec<WindowInfo> {
+        self.clients
+            .iter()
+            .map(|(window, client)| WindowInfo {
+                id: window.resource_id(),
+                title: client.title.clone(),
+                workspace: client.workspace,
+                focused: self.is_focused(*window),
+                floating: client.floating,
+                urgent: self.is_urgent(*window),
+            })
+            .collect()
+    }
 }
@@ -0,0 +1,76 @@
+use xcb::{x, Connection};
+
+use crate::window_geometry::Quadrant;
+
+// Glyph indices in the standard X "cursor" font, see `X11/cursorfont.h`
+const XC_BOTTOM_LEFT_CORNER: u16 = 12;
+const XC_BOTTOM_RIGHT_CORNER: u16 = 14;
+const XC_FLEUR: u16 = 52;
+const XC_LEFT_PTR: u16 = 68;
+const XC_TOP_LEFT_CORNER: u16 = 134;
+const XC_TOP_RIGHT_CORNER: u16 = 136;
+
+/// Cursors loaded from the X cursor font
+pub struct Cursors {
+    /// The default pointer, shown on the root window and frames
+    pub normal: x::Cursor,
+    /// Shown while moving a window
+    pub moving: x::Cursor,
+    top_left: x::Cursor,
+    top_right: x::Cursor,
+    bottom_left: x::Cursor,
+    bottom_right: x::Cursor,
+}
+
+impl Cursors {
+    pub fn new(conn: &Connection) -> xcb::Result<Cursors> {
+        let font = conn.generate_id();
+        conn.send_and_check_request(&x::OpenFont {
+            fid: font,
+            name: b"cursor",
+        })?;
+
+        // Each glyph in the cursor font is followed by its mask
+        let create = |glyph: u16| -> xcb::Result<x::Cursor> {
+            let cursor = conn.generate_id();
+            conn.send_and_check_request(&x::CreateGlyphCursor {
+                cid: cursor,
+                source_font: font,
+                mask_font: font,
+                source_char: glyph,
+                mask_char: glyph + 1,
+                fore_red: 0,
+                fore_green: 0,
+                fore_blue: 0,
+                back_red: u16::MAX,
+                back_green: u16::MAX,
+                back_blue: u16::MAX,
+            })?;
+            Ok(cursor)
+        };
+
+        let cursors = Cursors {
+            normal: create(XC_LEFT_PTR)?,
+            moving: create(XC_FLEUR)?,
+            top_left: create(XC_TOP_LEFT_CORNER)?,
+            top_right: create(XC_TOP_RIGHT_CORNER)?,
+            bottom_left: create(XC_BOTTOM_LEFT_CORNER)?,
+            bottom_right: create(XC_BOTTOM_RIGHT_CORNER)?,
+        };
+
+        // The cursors keep their own reference to the font
+        conn.send_and_check_request(&x::CloseFont { font })?;
+
+        Ok(cursors)
+    }
+
+    /// The cursor shown while resizing a window from the given quadrant
+    pub fn resizing(&self, quadrant: Quadrant) -> x::Cursor {
+        match quadrant {
+            Quadrant::TopLeft => self.top_left,
+            Quadrant::TopRight => self.top_right,
+            Quadrant::BottomLeft => self.bottom_left,
+            Quadrant::BottomRight => self.bottom_right,
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use xcb::x;
+
+use super::WindowManager;
+use crate::ret_ok_if_none;
+
+/// Height of each title listed in the overlay, in addition to the font's height
+const LINE_PADDING: u16 = 6;
+/// Width of the overlay, in characters
+const OVERLAY_CHARS: u16 = 48;
+
+/// State of an in-progress "Alt-Tab" cycle through recently focused windows
+pub struct FocusCycle {
+    /// The windows being cycled through, most recently focused first
+    windows: Vec<x::Window>,
+    /// Index into `windows` of the currently selected window
+    selected: usize,
+    /// The override-redirect window listing the titles of `windows`
+    overlay: x::Window,
+    /// Keycodes of the modifier which is held while cycling: when one is released, the cycle ends
+    modifier_keycodes: Vec<x::Keycode>,
+}
+
+impl FocusCycle {
+    fn step(&mut self, forward: bool) {
+        let len = self.windows.len();
+        self.selected = match forward {
+            true => (self.selected + 1) % len,
+            false => (self.selected + len - 1) % len,
+        };
+    }
+}
+
+impl<'a> WindowManager<'a> {
+    /// Select the next (or previous) window in the current workspace's focus history. If the cycle modifier
+    /// is held, an overlay lists the windows and focus isn't changed until the modifier is released; otherwise
+    /// the selected window is focused straight away.
+    pub(super) fn cycle_focus(&mut self, forward: bool) -> xcb::Result<()> {
+        if let Some(cycle) = self.focus_cycle.as_mut() {
+            cycle.step(forward);
+            return self.draw_cycle_overlay();
+        }
+
+        let windows = self.workspaces[self.current_workspace]
+            .focus_history
+            .iter_recent()
+            .filter(|w| self.clients.contains_key(w))
+            .collect::<Vec<_>>();
+        if windows.len() < 2 {
+            return Ok(());
+        }
+
+        let root = self.get_root_window()?;
+        let overlay = self.create_cycle_overlay(windows.len())?;
+        let mut cycle = FocusCycle {
+            windows,
+            selected: 0,
+            overlay,
+            modifier_keycodes: self.get_modifier_keycodes()?,
+        };
+        cycle.step(forward);
+        self.focus_cycle = Some(cycle);
+
+        // Grab the keyboard so we see the modifier being released, even if the keys were pressed in another client
+        let pointer = self
+            .conn
+            .wait_for_reply(self.conn.send_request(&x::QueryPointer { window: root }))?;
+        let modifier_held = pointer.mask().contains(self.config.cycle_modifier.key_but_mask());
+        let grab = self.conn.wait_for_reply(self.conn.send_request(&x::GrabKeyboard {
+            owner_events: false,
+            grab_window: root,
+            time: x::CURRENT_TIME,
+            pointer_mode: x::GrabMode::Async,
+            keyboard_mode: x::GrabMode::Async,
+        }))?;
+
+        // If we can't tell when the modifier is released, then just focus the selection right away
+        if !modifier_held || grab.status() != x::GrabStatus::Success {
+            return self.end_focus_cycle(true);
+        }
+
+        self.conn.send_and_check_request(&x::MapWindow { window: overlay })?;
+        self.draw_cycle_overlay()
+    }
+
+    /// Finish cycling: remove the overlay, and focus and raise the selected window if `commit` is set.
+    pub(super) fn end_focus_cycle(&mut self, commit: bool) -> xcb::Result<()> {
+        let cycle = ret_ok_if_none!(self.focus_cycle.take());
+        self.conn
+            .send_and_check_request(&x::DestroyWindow { window: cycle.overlay })?;
+        self.conn
+            .send_and_check_request(&x::UngrabKeyboard { time: x::CURRENT_TIME })?;
+
+        let window = cycle.windows[cycle.selected];
+        if commit && self.clients.contains_key(&window) {
+            // Focused windows are raised in `render`
+            self.focus_window(Some(window));
+        }
+
+        Ok(())
+    }
+
+    /// Called on each KeyPress while cycling: Tab steps through the windows (Shift+Tab steps backwards) and
+    /// Escape cancels. Returns true if the key was used.
+    pub(super) fn on_cycle_key_press(&mut self, ev: &x::KeyPressEvent) -> xcb::Result<bool> {
+        if self.focus_cycle.is_none() {
+            return Ok(false);
+        }
+
+        // TODO: support keymaps, these are Tab and Escape on qwerty
+        match ev.detail() {
+            0x17 => self.cycle_focus(!ev.state().contains(x::KeyButMask::SHIFT))?,
+            0x09 => self.end_focus_cycle(false)?,
+            _ => {}
+        }
+
+        Ok(true)
+    }
+
+    /// Called on each KeyRelease: releasing the modifier ends the cycle and focuses the selected window.
+    pub(super) fn on_cycle_key_release(&mut self, ev: &x::KeyReleaseEvent) -> xcb::Result<()> {
+        let cycle = ret_ok_if_none!(self.focus_cycle.as_ref());
+        if cycle.modifier_keycodes.contains(&ev.detail()) {
+            self.end_focus_cycle(true)?;
+        }
+
+        Ok(())
+    }
+
+    /// The keycodes which are mapped to the configured modifier
+    fn get_modifier_keycodes(&self) -> xcb::Result<Vec<x::Keycode>> {
+        let mapping = self
+            .conn
+            .wait_for_reply(self.conn.send_request(&x::GetModifierMapping {}))?;
+
+        // Keycodes are listed for each of the 8 modifiers in turn, with 0 for unused slots
+        let keycodes = mapping.keycodes();
+        let per_modifier = keycodes.len() / 8;
+        let start = self.config.cycle_modifier.index() * per_modifier;
+        Ok(keycodes[start..start + per_modifier]
+            .iter()
+            .copied()
+            .filter(|k| *k != 0)
+            .collect())
+    }
+
+    fn overlay_line_height(&self) -> u16 {
+        (self.decorations.ascent + self.decorations.descent) as u16 + LINE_PADDING
+    }
+
+    /// Create (but don't map) the overlay, centred on the screen
+    fn create_cycle_overlay(&self, lines: usize) -> xcb::Result<x::Window> {
+        let root = self.get_root_window()?;
+        let root_rect = self.get_window_rect(root)?;
+        let width = OVERLAY_CHARS * self.decorations.char_width;
+        let height = lines as u16 * self.overlay_line_height();
+
+        let overlay = self.conn.generate_id();
+        self.conn.send_and_check_request(&x::CreateWindow {
+            depth: x::COPY_FROM_PARENT as u8,
+            visual: x::COPY_FROM_PARENT,
+            wid: overlay,
+            parent: root,
+            x: (root_rect.w as i16 - width as i16) / 2,
+            y: (root_rect.h as i16 - height as i16) / 2,
+            width,
+            height,
+            border_width: self.theme.border_width,
+            class: x::WindowClass::InputOutput,
+            value_list: &[
+                x::Cw::BackPixel(self.theme.unfocused.background.0),
+                x::Cw::BorderPixel(self.theme.focused.border.0),
+                x::Cw::OverrideRedirect(true),
+                x::Cw::EventMask(x::EventMask::EXPOSURE),
+            ],
+        })?;
+
+        Ok(overlay)
+    }
+
+    /// Draw the overlay: each window's title, with the selected one highlighted
+    pub(super) fn draw_cycle_overlay(&self) -> xcb::Result<()> {
+        let cycle = ret_ok_if_none!(self.focus_cycle.as_ref());
+        let d = &self.decorations;
+        let line_height = self.overlay_line_height();
+        let width = OVERLAY_CHARS * d.char_width;
+
+        self.conn.send_request(&x::ClearArea {
+            exposures: false,
+            window: cycle.overlay,
+            x: 0,
+            y: 0,
+            width: 0,
+            height: 0,
+        });
+
+        for (i, window) in cycle.windows.iter().enumerate() {
+            let title = self.clients.get(window).map_or("", |c| c.title.as_str());
+            let colors = match i == cycle.selected {
+                true => &self.theme.focused,
+                false => &self.theme.unfocused,
+            };
+            let y = i as i16 * line_height as i16;
+
+            self.conn.send_request(&x::ChangeGc {
+                gc: d.gc,
+                value_list: &[x::Gc::Foreground(colors.background.0)],
+            });
+            self.conn.send_request(&x::PolyFillRectangle {
+                drawable: x::Drawable::Window(cycle.overlay),
+                gc: d.gc,
+                rectangles: &[x::Rectangle {
+                    x: 0,
+                    y,
+                    width,
+                    height: line_height,
+                }],
+            });
+            self.draw_text(
+                x::Drawable::Window(cycle.overlay),
+                colors,
+                self.theme.padding as i16,
+                y + (line_height as i16 + d.ascent - d.descent) / 2,
+                title,
+            );
+        }
+
+        self.conn.flush()?;
+
+        Ok(())
+    }
+
+    /// Is the window the focus cycle overlay?
+    pub(super) fn is_cycle_overlay(&self, window: x::Window) -> bool {
+        self.focus_cycle.as_ref().is_some_and(|c| c.overlay == window)
+    }
+}
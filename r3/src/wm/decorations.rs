@@ -0,0 +1,425 @@
+use xcb::{x, Connection};
+
+use super::client::Client;
+use super::masks::MASKS;
+use super::WindowManager;
+use crate::config::{TitleBarButton, TitleBarButtonSide};
+use crate::ret_ok_if_none;
+use crate::theme::{ColorSet, Theme};
+
+/// Space between title bar buttons, and between the buttons and the edges of the title bar
+const BUTTON_MARGIN: u16 = 3;
+
+/// X resources used to draw frame decorations
+pub struct Decorations {
+    /// Graphics context used to draw decorations (its font is set to the theme's font)
+    pub gc: x::Gcontext,
+    /// Ascent of the loaded font, used to vertically centre text
+    pub ascent: i16,
+    /// Descent of the loaded font, used to vertically centre text
+    pub descent: i16,
+    /// Width of the widest character in the loaded font
+    pub char_width: u16,
+}
+
+impl Decorations {
+    pub fn new(conn: &Connection, root: x::Window, font_name: &str) -> xcb::Result<Decorations> {
+        // Load the configured font, and fall back to "fixed" which every X server is required to provide
+        let font = conn.generate_id();
+        if conn
+            .send_and_check_request(&x::OpenFont {
+                fid: font,
+                name: font_name.as_bytes(),
+            })
+            .is_err()
+        {
+            log::warn!("Failed to open font {:?}, falling back to \"fixed\"", font_name);
+            conn.send_and_check_request(&x::OpenFont {
+                fid: font,
+                name: b"fixed",
+            })?;
+        }
+
+        let font_info = conn.wait_for_reply(conn.send_request(&x::QueryFont {
+            font: x::Fontable::Font(font),
+        }))?;
+
+        let gc = conn.generate_id();
+        conn.send_and_check_request(&x::CreateGc {
+            cid: gc,
+            drawable: x::Drawable::Window(root),
+            value_list: &[x::Gc::Font(font), x::Gc::GraphicsExposures(false)],
+        })?;
+
+        // The GC keeps its own reference to the font, so we can release ours
+        conn.send_and_check_request(&x::CloseFont { font })?;
+
+        Ok(Decorations {
+            gc,
+            ascent: font_info.font_ascent(),
+            descent: font_info.font_descent(),
+            char_width: font_info.max_bounds().character_width.max(1) as u16,
+        })
+    }
+
+    /// Release the X resources held by these decorations
+    pub fn free(&self, conn: &Connection) {
+        conn.send_request(&x::FreeGc { gc: self.gc });
+    }
+}
+
+/// Core X fonts drawn with `ImageText8` only understand Latin-1, so anything outside of it is replaced.
+/// `ImageText8` also can't draw more than 255 characters at once.
+fn to_latin1(s: &str) -> Vec<u8> {
+    s.chars()
+        .map(|c| if (c as u32) < 0x100 { c as u8 } else { b'?' })
+        .take(255)
+        .collect()
+}
+
+/// Whether a title bar button is drawn as active, showing the state it toggles is on.
+pub(super) fn is_button_active(client: &Client, kind: TitleBarButton) -> bool {
+    match kind {
+        TitleBarButton::Close => false,
+        TitleBarButton::Maximize => client.unmaximized_rect.is_some(),
+        TitleBarButton::Float => client.floating,
+    }
+}
+
+/// A window's title from its `_NET_WM_NAME` and `WM_NAME` properties.
+pub(super) fn window_title(net_wm_name: &x::GetPropertyReply, wm_name: &x::GetPropertyReply) -> String {
+    if !net_wm_name.value::<u8>().is_empty() {
+        return String::from_utf8_lossy(net_wm_name.value()).into_owned();
+    }
+
+    // WM_NAME is usually a STRING (Latin-1)
+    wm_name.value::<u8>().iter().map(|b| *b as char).collect()
+}
+
+impl<'a> WindowManager<'a> {
+    /// Read a window's title: `_NET_WM_NAME` (UTF-8) is preferred, and `WM_NAME` is used as a fallback.
+    pub(super) fn get_window_title(&self, window: x::Window) -> xcb::Result<String> {
+        let (net_wm_name, wm_name) = self.request_window_title(window);
+        Ok(window_title(
+            &self.conn.wait_for_reply(net_wm_name)?,
+            &self.conn.wait_for_reply(wm_name)?,
+        ))
+    }
+
+    /// Request the properties a window's title is read from, to be passed to `window_title`.
+    pub(super) fn request_window_title(&self, window: x::Window) -> (x::GetPropertyCookie, x::GetPropertyCookie) {
+        let net_wm_name = self.conn.send_request(&x::GetProperty {
+            delete: false,
+            window,
+            property: self.atoms.net_wm_name,
+            r#type: self.atoms.utf8_string,
+            long_offset: 0,
+            long_length: u32::MAX,
+        });
+        let wm_name = self.conn.send_request(&x::GetProperty {
+            delete: false,
+            window,
+            property: x::ATOM_WM_NAME,
+            r#type: x::ATOM_ANY,
+            long_offset: 0,
+            long_length: u32::MAX,
+        });
+
+        (net_wm_name, wm_name)
+    }
+
+    /// Re-read the window's title. Its title bar is redrawn when we next render.
+    pub(super) fn update_window_title(&mut self, window: x::Window) -> xcb::Result<()> {
+        let title = self.get_window_title(window)?;
+        let client = ret_ok_if_none!(self.clients.get_mut(&window));
+        client.title = title;
+
+        Ok(())
+    }
+
+    /// The colours a window's frame should currently be drawn with.
+    pub(super) fn frame_colors(&self, window: x::Window) -> &ColorSet {
+        if self.is_focused(window) {
+            &self.theme.focused
+        } else if self.is_urgent(window) {
+            &self.theme.urgent
+        } else {
+            &self.theme.unfocused
+        }
+    }
+
+    /// Draw the title bar of the frame around the given window.
+    pub(super) fn draw_title_bar(&self, window: x::Window) -> xcb::Result<()> {
+        let frame = *ret_ok_if_none!(self.framed_clients.get_by_left(&window));
+        let client = ret_ok_if_none!(self.clients.get(&window));
+        let colors = self.frame_colors(window);
+
+        // Clear the title bar (a width of 0 clears to the edge of the window)
+        let title_bar_height = self.theme.title_bar_height;
+        self.conn.send_request(&x::ChangeWindowAttributes {
+            window: frame,
+            value_list: &[x::Cw::BackPixel(colors.background.0)],
+        });
+        self.conn.send_request(&x::ClearArea {
+            exposures: false,
+            window: frame,
+            x: 0,
+            y: 0,
+            width: 0,
+            height: title_bar_height,
+        });
+
+        // Draw the title, vertically centred (and after the buttons if they're on the left)
+        let d = &self.decorations;
+        let padding = self.theme.padding as i16;
+        let text_x = match self.config.title_bar_button_side {
+            TitleBarButtonSide::Left => self.title_bar_buttons_width() as i16 + padding,
+            TitleBarButtonSide::Right => padding,
+        };
+        self.draw_text(
+            x::Drawable::Window(frame),
+            colors,
+            text_x,
+            (title_bar_height as i16 + d.ascent - d.descent) / 2,
+            &client.title,
+        );
+
+        for button in &client.buttons {
+            self.draw_title_bar_button(*button)?;
+        }
+
+        Ok(())
+    }
+
+    /// Draw text in the theme's font, with the given colours. `y` is the position of the text's baseline.
+    pub(super) fn draw_text(&self, drawable: x::Drawable, colors: &ColorSet, x: i16, y: i16, text: &str) {
+        let gc = self.decorations.gc;
+        self.conn.send_request(&x::ChangeGc {
+            gc,
+            value_list: &[x::Gc::Foreground(colors.text.0), x::Gc::Background(colors.background.0)],
+        });
+        self.conn.send_request(&x::ImageText8 {
+            drawable,
+            gc,
+            x,
+            y,
+            string: &to_latin1(text),
+        });
+    }
+
+    /// Size of each (square) title bar button
+    fn title_bar_button_size(&self) -> u16 {
+        self.theme.title_bar_height.saturating_sub(BUTTON_MARGIN * 2).max(1)
+    }
+
+    /// Total width taken up by the title bar buttons, including margins
+    fn title_bar_buttons_width(&self) -> u16 {
+        let count = self.config.title_bar_buttons.len() as u16;
+        count * (self.title_bar_button_size() + BUTTON_MARGIN) + BUTTON_MARGIN
+    }
+
+    /// Create the configured title bar buttons as child windows of the frame. Each button is its own window
+    /// so X reports Enter/Leave events for it, which we use to track hover state. The requests are added to
+    /// `cookies` rather than checked, so the caller can check them along with its own.
+    pub(super) fn create_title_bar_buttons(
+        &mut self,
+        window: x::Window,
+        frame: x::Window,
+        frame_width: u16,
+        cookies: &mut Vec<xcb::VoidCookieChecked>,
+    ) -> Vec<x::Window> {
+        let size = self.title_bar_button_size();
+        let side = self.config.title_bar_button_side;
+        let buttons_width = self.title_bar_buttons_width() as i16;
+
+        let mut buttons = vec![];
+        for (i, kind) in self.config.title_bar_buttons.clone().into_iter().enumerate() {
+            let offset = (BUTTON_MARGIN + i as u16 * (size + BUTTON_MARGIN)) as i16;
+            // Buttons on the right follow the right edge of the frame when it's resized
+            let (x, gravity) = match side {
+                TitleBarButtonSide::Left => (offset, x::Gravity::NorthWest),
+                TitleBarButtonSide::Right => (frame_width as i16 - buttons_width + offset, x::Gravity::NorthEast),
+            };
+
+            let button = self.conn.generate_id();
+            cookies.push(self.conn.send_request_checked(&x::CreateWindow {
+                depth: x::COPY_FROM_PARENT as u8,
+                visual: x::COPY_FROM_PARENT,
+                wid: button,
+                parent: frame,
+                x,
+                y: BUTTON_MARGIN as i16,
+                width: size,
+                height: size,
+                border_width: 0,
+                class: x::WindowClass::InputOutput,
+                value_list: &[
+                    x::Cw::WinGravity(gravity),
+                    x::Cw::EventMask(MASKS.title_bar_button_events),
+                ],
+            }));
+            cookies.push(self.conn.send_request_checked(&x::MapWindow { window: button }));
+
+            self.title_bar_buttons.insert(button, (window, kind));
+            buttons.push(button);
+        }
+
+        buttons
+    }
+
+    /// Draw a single title bar button, reflecting its hover state and the state of its window.
+    pub(super) fn draw_title_bar_button(&self, button: x::Window) -> xcb::Result<()> {
+        let (window, kind) = *ret_ok_if_none!(self.title_bar_buttons.get(&button));
+        let client = ret_ok_if_none!(self.clients.get(&window));
+
+        let colors = self.frame_colors(window);
+        let hovered = self.hovered_button == Some(button);
+        let background = match (hovered, is_button_active(client, kind)) {
+            (true, _) => colors.button_hover,
+            (false, true) => colors.button_active,
+            (false, false) => colors.button,
+        };
+
+        // Set the background and clear the button to paint it
+        self.conn.send_request(&x::ChangeWindowAttributes {
+            window: button,
+            value_list: &[x::Cw::BackPixel(background.0)],
+        });
+        self.conn.send_request(&x::ClearArea {
+            exposures: false,
+            window: button,
+            x: 0,
+            y: 0,
+            width: 0,
+            height: 0,
+        });
+
+        // Draw the button's icon
+        let drawable = x::Drawable::Window(button);
+        let gc = self.decorations.gc;
+        self.conn.send_request(&x::ChangeGc {
+            gc,
+            value_list: &[x::Gc::Foreground(colors.text.0)],
+        });
+        let (lo, hi) = (3, self.title_bar_button_size() as i16 - 4);
+        let len = (hi - lo).max(1) as u16;
+        match kind {
+            TitleBarButton::Close => {
+                self.conn.send_request(&x::PolySegment {
+                    drawable,
+                    gc,
+                    segments: &[
+                        x::Segment {
+                            x1: lo,
+                            y1: lo,
+                            x2: hi,
+                            y2: hi,
+                        },
+                        x::Segment {
+                            x1: lo,
+                            y1: hi,
+                            x2: hi,
+                            y2: lo,
+                        },
+                    ],
+                });
+            }
+            TitleBarButton::Maximize => {
+                self.conn.send_request(&x::PolyRectangle {
+                    drawable,
+                    gc,
+                    rectangles: &[x::Rectangle {
+                        x: lo,
+                        y: lo,
+                        width: len,
+                        height: len,
+                    }],
+                });
+            }
+            TitleBarButton::Float => {
+                // Two overlapping windows
+                let len = len.saturating_sub(2).max(1);
+                self.conn.send_request(&x::PolyRectangle {
+                    drawable,
+                    gc,
+                    rectangles: &[
+                        x::Rectangle {
+                            x: lo,
+                            y: lo + 2,
+                            width: len,
+                            height: len,
+                        },
+                        x::Rectangle {
+                            x: lo + 2,
+                            y: lo,
+                            width: len,
+                            height: len,
+                        },
+                    ],
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Perform the action of a title bar button that was clicked.
+    pub(super) fn on_title_bar_button_click(&mut self, button: x::Window) -> xcb::Result<()> {
+        let (window, kind) = *ret_ok_if_none!(self.title_bar_buttons.get(&button));
+        match kind {
+            TitleBarButton::Close => self.kill_window(window)?,
+            TitleBarButton::Maximize => self.toggle_maximize(window)?,
+            TitleBarButton::Float => self.toggle_floating(window)?,
+        }
+
+        Ok(())
+    }
+
+    /// Switch to a new theme, and re-apply it to every frame: borders, title bar heights and buttons are updated
+    /// around each window without changing the window's own size.
+    pub(super) fn set_theme(&mut self, theme: Theme) -> xcb::Result<()> {
+        let decorations = Decorations::new(self.conn, self.get_root_window()?, &theme.font)?;
+        std::mem::replace(&mut self.decorations, decorations).free(self.conn);
+        self.theme = theme;
+
+        let title_bar_height = self.theme.title_bar_height;
+        let framed_clients = self.framed_clients.iter().map(|(w, f)| (*w, *f)).collect::<Vec<_>>();
+        for (window, frame) in framed_clients {
+            let rect = self.get_window_rect(window)?;
+            let border_width = match self.clients.get(&window).is_some_and(|c| c.no_border) {
+                true => 0,
+                false => self.theme.border_width,
+            };
+            self.conn.send_and_check_request(&x::ConfigureWindow {
+                window: frame,
+                value_list: &[
+                    x::ConfigWindow::Height(rect.h.saturating_add(title_bar_height).into()),
+                    x::ConfigWindow::BorderWidth(border_width.into()),
+                ],
+            })?;
+            self.conn.send_and_check_request(&x::ConfigureWindow {
+                window,
+                value_list: &[x::ConfigWindow::Y(title_bar_height.into())],
+            })?;
+
+            // Button sizes depend on the title bar's height, so re-create them
+            let old_buttons = self.clients.get_mut(&window).map(|c| std::mem::take(&mut c.buttons));
+            for button in old_buttons.unwrap_or_default() {
+                self.title_bar_buttons.remove(&button);
+                self.conn.send_request(&x::DestroyWindow { window: button });
+            }
+            let mut cookies = vec![];
+            let buttons = self.create_title_bar_buttons(window, frame, rect.w, &mut cookies);
+            if let Some(client) = self.clients.get_mut(&window) {
+                client.buttons = buttons;
+            }
+            for cookie in cookies {
+                self.conn.check_request(cookie)?;
+            }
+
+            self.rendered.invalidate_title_bar(window);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,239 @@
+use xcb::{x, Xid};
+
+use super::WindowManager;
+use crate::logging;
+
+/// Names of the core X protocol requests, indexed by major opcode
+const REQUEST_NAMES: &[&str] = &[
+    "", // Opcodes start at 1
+    "CreateWindow",
+    "ChangeWindowAttributes",
+    "GetWindowAttributes",
+    "DestroyWindow",
+    "DestroySubwindows",
+    "ChangeSaveSet",
+    "ReparentWindow",
+    "MapWindow",
+    "MapSubwindows",
+    "UnmapWindow",
+    "UnmapSubwindows",
+    "ConfigureWindow",
+    "CirculateWindow",
+    "GetGeometry",
+    "QueryTree",
+    "InternAtom",
+    "GetAtomName",
+    "ChangeProperty",
+    "DeleteProperty",
+    "GetProperty",
+    "ListProperties",
+    "SetSelectionOwner",
+    "GetSelectionOwner",
+    "ConvertSelection",
+    "SendEvent",
+    "GrabPointer",
+    "UngrabPointer",
+    "GrabButton",
+    "UngrabButton",
+    "ChangeActivePointerGrab",
+    "GrabKeyboard",
+    "UngrabKeyboard",
+    "GrabKey",
+    "UngrabKey",
+    "AllowEvents",
+    "GrabServer",
+    "UngrabServer",
+    "QueryPointer",
+    "GetMotionEvents",
+    "TranslateCoordinates",
+    "WarpPointer",
+    "SetInputFocus",
+    "GetInputFocus",
+    "QueryKeymap",
+    "OpenFont",
+    "CloseFont",
+    "QueryFont",
+    "QueryTextExtents",
+    "ListFonts",
+    "ListFontsWithInfo",
+    "SetFontPath",
+    "GetFontPath",
+    "CreatePixmap",
+    "FreePixmap",
+    "CreateGC",
+    "ChangeGC",
+    "CopyGC",
+    "SetDashes",
+    "SetClipRectangles",
+    "FreeGC",
+    "ClearArea",
+    "CopyArea",
+    "CopyPlane",
+    "PolyPoint",
+    "PolyLine",
+    "PolySegment",
+    "PolyRectangle",
+    "PolyArc",
+    "FillPoly",
+    "PolyFillRectangle",
+    "PolyFillArc",
+    "PutImage",
+    "GetImage",
+    "PolyText8",
+    "PolyText16",
+    "ImageText8",
+    "ImageText16",
+    "CreateColormap",
+    "FreeColormap",
+    "CopyColormapAndFree",
+    "InstallColormap",
+    "UninstallColormap",
+    "ListInstalledColormaps",
+    "AllocColor",
+    "AllocNamedColor",
+    "AllocColorCells",
+    "AllocColorPlanes",
+    "FreeColors",
+    "StoreColors",
+    "StoreNamedColor",
+    "QueryColors",
+    "LookupColor",
+    "CreateCursor",
+    "CreateGlyphCursor",
+    "FreeCursor",
+    "RecolorCursor",
+    "QueryBestSize",
+    "QueryExtension",
+    "ListExtensions",
+    "ChangeKeyboardMapping",
+    "GetKeyboardMapping",
+    "ChangeKeyboardControl",
+    "GetKeyboardControl",
+    "Bell",
+    "ChangePointerControl",
+    "GetPointerControl",
+    "SetScreenSaver",
+    "GetScreenSaver",
+    "ChangeHosts",
+    "ListHosts",
+    "SetAccessControl",
+    "SetCloseDownMode",
+    "KillClient",
+    "RotateProperties",
+    "ForceScreenSaver",
+    "SetPointerMapping",
+    "GetPointerMapping",
+    "SetModifierMapping",
+    "GetModifierMapping",
+];
+
+/// The name of a core X request, or "Unknown" for extension requests (which have opcodes of 128 and up)
+fn request_name(major_opcode: u8) -> &'static str {
+    match major_opcode {
+        127 => "NoOperation",
+        opcode => REQUEST_NAMES.get(opcode as usize).copied().unwrap_or("Unknown"),
+    }
+}
+
+/// The details of an error from the X server that are useful for logging and recovering from it
+struct XError {
+    /// The error's name, e.g. "BadWindow"
+    name: &'static str,
+    major_opcode: u8,
+    minor_opcode: u16,
+    /// For errors about a resource (e.g. a window), this is its id
+    bad_value: u32,
+    sequence: u16,
+}
+
+impl XError {
+    fn new(err: &x::Error) -> XError {
+        macro_rules! details {
+            ($name:expr, $e:expr) => {
+                XError {
+                    name: $name,
+                    major_opcode: $e.major_opcode(),
+                    minor_opcode: $e.minor_opcode(),
+                    bad_value: $e.bad_value(),
+                    sequence: $e.sequence(),
+                }
+            };
+        }
+
+        match err {
+            x::Error::Request(e) => details!("BadRequest", e),
+            x::Error::Value(e) => details!("BadValue", e),
+            x::Error::Window(e) => details!("BadWindow", e),
+            x::Error::Pixmap(e) => details!("BadPixmap", e),
+            x::Error::Atom(e) => details!("BadAtom", e),
+            x::Error::Cursor(e) => details!("BadCursor", e),
+            x::Error::Font(e) => details!("BadFont", e),
+            x::Error::Match(e) => details!("BadMatch", e),
+            x::Error::Drawable(e) => details!("BadDrawable", e),
+            x::Error::Access(e) => details!("BadAccess", e),
+            x::Error::Alloc(e) => details!("BadAlloc", e),
+            x::Error::Colormap(e) => details!("BadColormap", e),
+            x::Error::GContext(e) => details!("BadGC", e),
+            x::Error::IdChoice(e) => details!("BadIDChoice", e),
+            x::Error::Name(e) => details!("BadName", e),
+            x::Error::Length(e) => details!("BadLength", e),
+            x::Error::Implementation(e) => details!("BadImplementation", e),
+        }
+    }
+}
+
+impl<'a> WindowManager<'a> {
+    /// Recover from the errors of an event or command handler. X protocol errors are routine (e.g., a client may
+    /// destroy its window while we're in the middle of managing it), so they're logged and r3 carries on. Only a
+    /// failure of the connection itself is returned.
+    pub(super) fn recover(&mut self, result: xcb::Result<()>) -> xcb::Result<()> {
+        match result {
+            Ok(()) => Ok(()),
+            Err(xcb::Error::Protocol(xcb::ProtocolError::X(err, _))) => {
+                self.on_x_error(&err);
+                Ok(())
+            }
+            Err(xcb::Error::Protocol(err)) => {
+                log::warn!(target: logging::X, "X error: {:?}", err);
+                Ok(())
+            }
+            Err(err @ xcb::Error::Connection(_)) => Err(err),
+        }
+    }
+
+    fn on_x_error(&mut self, err: &x::Error) {
+        let details = XError::new(err);
+        log::warn!(
+            target: logging::X,
+            "X error: {} (resource 0x{:x}) from {} ({}.{}), sequence {}",
+            details.name,
+            details.bad_value,
+            request_name(details.major_opcode),
+            details.major_opcode,
+            details.minor_opcode,
+            details.sequence,
+        );
+
+        // The window no longer exists, so stop managing it
+        if matches!(err, x::Error::Window(_) | x::Error::Drawable(_)) {
+            self.forget_destroyed_window(details.bad_value);
+        }
+    }
+
+    /// Forget a client whose window (or frame) has been destroyed without us noticing, and clean up its frame.
+    fn forget_destroyed_window(&mut self, resource_id: u32) {
+        let framed = self
+            .framed_clients
+            .iter()
+            .find(|(w, f)| w.resource_id() == resource_id || f.resource_id() == resource_id)
+            .map(|(w, f)| (*w, *f));
+
+        if let Some((window, frame)) = framed {
+            log::info!(target: logging::X, "Window 0x{:x} is gone, forgetting it", window.resource_id());
+            // If the frame still exists, it's no use anymore (any error from this is also ignored)
+            self.conn.send_request(&x::DestroyWindow { window: frame });
+            self.framed_clients.remove_by_left(&window);
+            self.forget_client(window);
+        }
+    }
+}
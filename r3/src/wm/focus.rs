@@ -0,0 +1,153 @@
+use xcb::{x, Xid};
+
+use super::WindowManager;
+use crate::logging;
+
+/// A most-recently-used history of focused windows
+#[derive(Debug, Default)]
+pub struct FocusHistory {
+    /// Ordered from least to most recently focused
+    windows: Vec<x::Window>,
+}
+
+impl FocusHistory {
+    /// Record that the window was focused, making it the most recent
+    pub fn push(&mut self, window: x::Window) {
+        self.remove(window);
+        self.windows.push(window);
+    }
+
+    pub fn remove(&mut self, window: x::Window) {
+        self.windows.retain(|w| *w != window);
+    }
+
+    /// The most recently focused window
+    pub fn last(&self) -> Option<x::Window> {
+        self.windows.last().copied()
+    }
+
+    /// Iterate from the most to least recently focused window
+    pub fn iter_recent(&self) -> impl DoubleEndedIterator<Item = x::Window> + '_ {
+        self.windows.iter().rev().copied()
+    }
+}
+
+impl<'a> WindowManager<'a> {
+    /// Focus a window (or nothing) and record it in its workspace's focus history.
+    /// Focusing a frame focuses its window. The X input focus is updated in `render`.
+    pub(super) fn focus_window(&mut self, target: Option<x::Window>) {
+        let window = target.map(|t| self.get_frame_and_window(t).map_or(t, |(w, _)| w));
+        if let Some(window) = window {
+            if let Some(workspace) = self.clients.get(&window).map(|c| c.workspace) {
+                self.workspaces[workspace].focus_history.push(window);
+            }
+        }
+
+        if window != self.focused_window {
+            log::debug!(target: logging::FOCUS, "Focusing {:?}", window);
+        }
+        self.focused_window = window;
+    }
+
+    /// Focus the most recently focused window on the current workspace, or nothing if there isn't one.
+    pub(super) fn focus_previous(&mut self) {
+        let previous = self.workspaces[self.current_workspace].focus_history.last();
+        self.focus_window(previous);
+    }
+
+    /// Forget a window which is no longer managed, and focus another if it was focused.
+    pub(super) fn forget_focus(&mut self, window: x::Window) {
+        for workspace in &mut self.workspaces {
+            workspace.focus_history.remove(window);
+        }
+
+        if self.focused_window == Some(window) {
+            self.focus_previous();
+        }
+    }
+
+    /// Read which of the ICCCM input models a window uses: whether it accepts input focus (`WM_HINTS.input`), and
+    /// whether it wants to be told when to focus itself (`WM_TAKE_FOCUS`).
+    pub(super) fn get_focus_hints(&self, window: x::Window) -> xcb::Result<(bool, bool)> {
+        let wm_hints = self.request_wm_hints(window);
+        let protocols = self.request_protocols(window);
+
+        let accepts_input = accepts_input(&self.conn.wait_for_reply(wm_hints)?);
+        let takes_focus = self
+            .conn
+            .wait_for_reply(protocols)?
+            .value::<x::Atom>()
+            .contains(&self.atoms.wm_take_focus);
+
+        Ok((accepts_input, takes_focus))
+    }
+
+    pub(super) fn request_wm_hints(&self, window: x::Window) -> x::GetPropertyCookie {
+        self.conn.send_request(&x::GetProperty {
+            delete: false,
+            window,
+            property: x::ATOM_WM_HINTS,
+            r#type: x::ATOM_WM_HINTS,
+            long_offset: 0,
+            long_length: 9,
+        })
+    }
+
+    /// Give X's input focus to the focused window, according to its input model. The requests are sent unchecked,
+    /// and flushed when rendering finishes.
+    pub(super) fn apply_input_focus(&mut self) -> xcb::Result<()> {
+        self.rendered.set_focus(self.focused_window);
+
+        let window = match self.focused_window {
+            Some(window) => window,
+            // Nothing is focused, so let keyboard input follow the pointer
+            None => {
+                self.conn.send_request(&x::SetInputFocus {
+                    revert_to: x::InputFocus::PointerRoot,
+                    focus: self.get_root_window()?,
+                    time: x::CURRENT_TIME,
+                });
+                return Ok(());
+            }
+        };
+
+        self.clear_urgency(window)?;
+
+        let (accepts_input, takes_focus) = self
+            .clients
+            .get(&window)
+            .map_or((true, false), |c| (c.accepts_input, c.takes_focus));
+
+        if accepts_input {
+            self.conn.send_request(&x::SetInputFocus {
+                revert_to: x::InputFocus::PointerRoot,
+                focus: window,
+                time: x::CURRENT_TIME,
+            });
+        }
+
+        // ICCCM requires WM_TAKE_FOCUS to have the timestamp of the event which caused the focus change. Focus
+        // changed by IPC commands uses the latest event's, which is the closest we have.
+        if takes_focus {
+            let data =
+                x::ClientMessageData::Data32([self.atoms.wm_take_focus.resource_id(), self.last_event_time, 0, 0, 0]);
+            self.conn.send_request(&x::SendEvent {
+                propagate: false,
+                destination: x::SendEventDest::Window(window),
+                event_mask: x::EventMask::NO_EVENT,
+                event: &x::ClientMessageEvent::new(window, self.atoms.wm_protocols, data),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether a window accepts input focus, according to its `WM_HINTS`. If the window doesn't set the InputHint flag,
+/// assume it does.
+pub(super) fn accepts_input(wm_hints: &x::GetPropertyReply) -> bool {
+    match wm_hints.value::<u32>() {
+        [flags, input, ..] if flags & 1 != 0 => *input != 0,
+        _ => true,
+    }
+}
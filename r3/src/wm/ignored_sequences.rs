@@ -29,7 +29,7 @@ impl Inner {
 // Inner is sorted by store time
 impl PartialOrd for Inner {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        self.store_time.partial_cmp(&other.store_time)
+        Some(self.cmp(other))
     }
 }
 
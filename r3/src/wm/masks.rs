@@ -11,6 +11,8 @@ pub struct Masks {
     pub frame_window_events: EventMask,
     /// Events for the root window
     pub root_window_events: EventMask,
+    /// Events for the buttons in a frame's title bar
+    pub title_bar_button_events: EventMask,
 }
 
 impl Masks {
@@ -32,6 +34,11 @@ impl Masks {
                 | EventMask::PROPERTY_CHANGE
                 | EventMask::FOCUS_CHANGE
                 | EventMask::ENTER_WINDOW, // Pointer moved onto root window
+            title_bar_button_events: EventMask::BUTTON_PRESS // Button clicked
+                | EventMask::BUTTON_RELEASE              // Button released
+                | EventMask::EXPOSURE                    // Button needs to be redrawn
+                | EventMask::ENTER_WINDOW                // Pointer moved onto the button (hover)
+                | EventMask::LEAVE_WINDOW, // Pointer moved off the button
         }
     }
 }
@@ -1,23 +1,54 @@
+mod client;
 mod cmd_handlers;
+mod cursors;
+mod cycle;
+mod decorations;
+mod errors;
+mod focus;
 mod ignored_sequences;
 mod masks;
+mod mouse;
+mod navigation;
+mod render;
+mod restart;
+mod rules;
+mod scratchpad;
+mod selection;
+mod startup;
+mod sync;
+mod urgency;
 mod windows;
+mod workspaces;
 mod x_handlers;
 
+use std::collections::HashMap;
 use std::os::unix::prelude::OsStrExt;
 use std::path::Path;
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
+use std::time::Instant;
 
 use bimap::BiHashMap;
 use mio::Waker;
 use r3lib::R3Command;
 use xcb::{x, Connection};
 
+use self::client::Client;
+use self::cursors::Cursors;
+use self::cycle::FocusCycle;
+use self::decorations::Decorations;
 use self::ignored_sequences::IgnoredSequences;
 use self::masks::MASKS;
-use crate::config::Config;
+use self::render::RenderedState;
+pub use self::restart::SavedState;
+pub use self::selection::ManagerError;
+use self::selection::ManagerSelection;
+use self::startup::StartupNotifications;
+use self::workspaces::{Workspace, WORKSPACE_COUNT};
+use crate::config::{Config, TitleBarButton};
 use crate::point::Point;
+use crate::theme::Theme;
 use crate::window_geometry::WindowGeometry;
+use crate::CommandQueue;
 
 // TODO: see https://github.com/rust-x-bindings/rust-xcb/pull/182
 crate::atoms_struct! {
@@ -25,14 +56,25 @@ crate::atoms_struct! {
     struct Atoms {
         wm_protocols     => b"WM_PROTOCOLS"                 only_if_exists = false,
         wm_del_window    => b"WM_DELETE_WINDOW"             only_if_exists = false,
+        wm_take_focus    => b"WM_TAKE_FOCUS"                only_if_exists = false,
         #[allow(dead_code)]
         wm_active_window => b"_NET_ACTIVE_WINDOW"           only_if_exists = false,
-        #[allow(dead_code)]
         wm_state         => b"_NET_WM_STATE"                only_if_exists = false,
+        net_wm_state_demands_attention => b"_NET_WM_STATE_DEMANDS_ATTENTION" only_if_exists = false,
         #[allow(dead_code)]
         wm_state_maxv    => b"_NET_WM_STATE_MAXIMIZED_VERT" only_if_exists = false,
         #[allow(dead_code)]
         wm_state_maxh    => b"_NET_WM_STATE_MAXIMIZED_HORZ" only_if_exists = false,
+        net_wm_name      => b"_NET_WM_NAME"                 only_if_exists = false,
+        net_wm_window_type => b"_NET_WM_WINDOW_TYPE"        only_if_exists = false,
+        wm_window_role   => b"WM_WINDOW_ROLE"               only_if_exists = false,
+        net_startup_id   => b"_NET_STARTUP_ID"              only_if_exists = false,
+        net_startup_info_begin => b"_NET_STARTUP_INFO_BEGIN" only_if_exists = false,
+        net_startup_info => b"_NET_STARTUP_INFO"            only_if_exists = false,
+        net_wm_sync_request => b"_NET_WM_SYNC_REQUEST"      only_if_exists = false,
+        net_wm_sync_request_counter => b"_NET_WM_SYNC_REQUEST_COUNTER" only_if_exists = false,
+        utf8_string      => b"UTF8_STRING"                  only_if_exists = false,
+        manager          => b"MANAGER"                      only_if_exists = false,
 
         // Custom atoms
 
@@ -58,10 +100,12 @@ enum DragType {
 pub struct WindowManager<'a> {
     /// Our way of communicating back to the main loop
     ev_waker: Arc<Waker>,
-    ev_queue: Arc<Mutex<Vec<R3Command>>>,
+    ev_queue: CommandQueue,
 
     /// WM Configuration
     config: Config,
+    /// The theme frames are currently drawn with
+    theme: Theme,
 
     /// XCB connection
     conn: &'a Connection,
@@ -69,9 +113,23 @@ pub struct WindowManager<'a> {
     atoms: Atoms,
     /// X's default screen
     default_screen: i32,
+    /// Whether the X server has the XSync extension, which `_NET_WM_SYNC_REQUEST` needs
+    sync_available: bool,
 
     /// A mapping of Window -> Frame to help keep track of framed windows
     framed_clients: BiHashMap<x::Window, x::Window>,
+    /// State for each client window we've framed
+    clients: HashMap<x::Window, Client>,
+    /// X resources used to draw frame decorations
+    decorations: Decorations,
+    /// Cursors shown on the root window and while dragging windows
+    cursors: Cursors,
+    /// A mapping of title bar button -> (Window, kind of button)
+    title_bar_buttons: HashMap<x::Window, (x::Window, TitleBarButton)>,
+    /// The title bar button the pointer is currently over
+    hovered_button: Option<x::Window>,
+    /// The title bar button a mouse button was pressed on (it's clicked if it's released while still hovered)
+    pressed_button: Option<x::Window>,
     /// List of event sequences to ignore. Sometimes, X will trigger EnterNotify events for
     /// mapped (and unmapped!) windows; these events are indistinguishable from user-generated
     /// events, and don't provide any value for us. In these cases, we maintain a list of event
@@ -83,9 +141,36 @@ pub struct WindowManager<'a> {
     drag_start: Option<Point>,
     /// If a drag is in progress, this will contain the starting rect of the frame dragged
     drag_start_frame_rect: Option<WindowGeometry>,
+    /// If a drag is in progress, this will contain what it's doing
+    drag_type: Option<DragType>,
+    /// If a resize drag is in progress, the latest geometry of the window being resized, if it hasn't been
+    /// applied yet (see `drag_resize`)
+    pending_drag_resize: Option<(x::Window, WindowGeometry)>,
+    /// When the window being resized with a drag was last resized
+    last_drag_resize: Option<Instant>,
 
     /// The currently focused window
     focused_window: Option<x::Window>,
+    /// If the focus is being cycled through with the keyboard, the state of that cycle
+    focus_cycle: Option<FocusCycle>,
+    /// The server time of the latest event which had one, used to timestamp requests made because of it
+    last_event_time: x::Timestamp,
+    /// Windows which are demanding attention, in the order they started to
+    urgent_windows: Vec<x::Window>,
+    /// The urgent windows last reported to IPC subscribers
+    reported_urgent: Vec<x::Window>,
+    /// Windows which belong to the scratchpad, in the order they were added to it
+    scratchpad: Vec<x::Window>,
+    /// What was last sent to X about the frames, so rendering only sends what's changed
+    rendered: RenderedState,
+    /// Applications which are being launched, so their windows can be placed on the right workspace
+    startup: StartupNotifications,
+    /// The selection we own as the window manager
+    manager_selection: Option<ManagerSelection>,
+
+    /// All workspaces, and the index of the one that's currently shown
+    workspaces: Vec<Workspace>,
+    current_workspace: usize,
 }
 
 impl<'a> WindowManager<'a> {
@@ -93,52 +178,76 @@ impl<'a> WindowManager<'a> {
     /// It will not attempt to become the X Server's window manager until `.run()` is called.
     pub fn new(
         (conn, default_screen): (&'a Connection, i32),
-        (ev_waker, ev_queue): (Arc<Waker>, Arc<Mutex<Vec<R3Command>>>),
+        (ev_waker, ev_queue): (Arc<Waker>, CommandQueue),
+        config: Config,
     ) -> xcb::Result<WindowManager<'a>> {
-        let atoms = Atoms::intern_all(&conn)?;
+        let atoms = Atoms::intern_all(conn)?;
+        let theme = config.get_theme(&config.theme).unwrap_or_default();
+        let root = conn.get_setup().roots().nth(default_screen as usize).unwrap().root();
+        let decorations = Decorations::new(conn, root, &theme.font)?;
         Ok(WindowManager {
             ev_waker,
             ev_queue,
 
-            config: Config::new(),
+            config,
+            theme,
 
             conn,
             atoms,
             default_screen,
+            sync_available: sync::init_sync(conn),
 
             framed_clients: BiHashMap::new(),
+            clients: HashMap::new(),
+            decorations,
+            cursors: Cursors::new(conn)?,
+            title_bar_buttons: HashMap::new(),
+            hovered_button: None,
+            pressed_button: None,
             ignored_sequences: IgnoredSequences::new(),
 
             drag_start: None,
             drag_start_frame_rect: None,
+            drag_type: None,
+            pending_drag_resize: None,
+            last_drag_resize: None,
 
             focused_window: None,
+            focus_cycle: None,
+            last_event_time: x::CURRENT_TIME,
+            urgent_windows: vec![],
+            reported_urgent: vec![],
+            scratchpad: vec![],
+            rendered: RenderedState::default(),
+            startup: StartupNotifications::default(),
+            manager_selection: None,
+
+            workspaces: (0..WORKSPACE_COUNT).map(|_| Workspace::default()).collect(),
+            current_workspace: 0,
         })
     }
 
-    /// Become the window manager and setup root event masks
-    pub fn become_window_manager(&mut self, socket_path: &Path) -> xcb::Result<()> {
+    /// Become the window manager and setup root event masks. If another window manager is running, it's replaced
+    /// when `replace` is set, otherwise this fails.
+    pub fn become_window_manager(&mut self, socket_path: &Path, replace: bool) -> Result<(), ManagerError> {
         // Request to become the X window manager
+        self.acquire_manager_selection(replace)?;
         self.acquire_wm_event_mask()?;
 
         // Start managing any existing windows
         self.reparent_existing_windows()?;
 
         // Bind key events on root window so they're always reported
-        let root = self.get_root_window()?;
-        self.conn.send_and_check_request(&x::GrabKey {
-            grab_window: root,
-            owner_events: false,
-            key: 0x18, // Q on qwerty TODO: support keymaps
-            pointer_mode: x::GrabMode::Async,
-            keyboard_mode: x::GrabMode::Async,
-            modifiers: x::ModMask::ANY,
-        })?;
+        self.grab_keys()?;
 
-        // Start listening to events on the root window
+        // Start listening to events on the root window, and show the default cursor on the background
+        let root = self.get_root_window()?;
         self.conn.send_and_check_request(&x::ChangeWindowAttributes {
             window: root,
-            value_list: &[x::Cw::EventMask(MASKS.root_window_events)],
+            value_list: &[
+                x::Cw::EventMask(MASKS.root_window_events),
+                x::Cw::Cursor(self.cursors.normal),
+            ],
         })?;
 
         // Set an atom on the root window with the path to our IPC socket
@@ -159,6 +268,82 @@ impl<'a> WindowManager<'a> {
         Ok(())
     }
 
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Use a newly loaded configuration. Windows keep the state the previous configuration gave them (e.g., from
+    /// rules), but are redrawn with the new theme.
+    pub fn reload_config(&mut self, config: Config) -> xcb::Result<()> {
+        let result = self.apply_config(config);
+        self.recover(result)
+    }
+
+    fn apply_config(&mut self, config: Config) -> xcb::Result<()> {
+        let theme = match config.get_theme(&config.theme) {
+            Ok(theme) => theme,
+            Err(e) => {
+                log::error!("Failed to reload configuration: {}", e);
+                return Ok(());
+            }
+        };
+
+        self.conn.send_and_check_request(&x::UngrabKey {
+            key: x::GRAB_ANY,
+            grab_window: self.get_root_window()?,
+            modifiers: x::ModMask::ANY,
+        })?;
+        self.config = config;
+        self.grab_keys()?;
+        self.set_theme(theme)
+    }
+
+    /// Run a command from the main loop, just like those received over IPC
+    fn queue_command(&self, command: R3Command) {
+        // If another thread panicked while holding the lock, the queue itself is still usable
+        let mut commands = self.ev_queue.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        commands.push((command, None));
+        drop(commands);
+        if let Err(e) = self.ev_waker.wake() {
+            log::error!("Failed to wake the main loop: {}", e);
+        }
+    }
+
+    /// Grab the key combinations r3 handles on the root window
+    fn grab_keys(&self) -> xcb::Result<()> {
+        let root = self.get_root_window()?;
+        self.conn.send_and_check_request(&x::GrabKey {
+            grab_window: root,
+            owner_events: false,
+            key: 0x18, // Q on qwerty TODO: support keymaps
+            pointer_mode: x::GrabMode::Async,
+            keyboard_mode: x::GrabMode::Async,
+            modifiers: x::ModMask::ANY,
+        })?;
+        // Grabs only match the exact modifiers, so also grab with CapsLock (Lock) and NumLock (usually Mod2) on
+        let cycle_mask = self.config.cycle_modifier.mod_mask();
+        let locks = [
+            x::ModMask::empty(),
+            x::ModMask::LOCK,
+            x::ModMask::N2,
+            x::ModMask::LOCK | x::ModMask::N2,
+        ];
+        for modifiers in [cycle_mask, cycle_mask | x::ModMask::SHIFT] {
+            for lock in locks {
+                self.conn.send_and_check_request(&x::GrabKey {
+                    grab_window: root,
+                    owner_events: false,
+                    key: 0x17, // Tab on qwerty TODO: support keymaps
+                    pointer_mode: x::GrabMode::Async,
+                    keyboard_mode: x::GrabMode::Async,
+                    modifiers: modifiers | lock,
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+
     /// To be called just after becoming the X Server's window manager.
     /// This will iterate all existing X windows and frame them as needed.
     fn reparent_existing_windows(&mut self) -> xcb::Result<()> {
@@ -181,9 +366,9 @@ impl<'a> WindowManager<'a> {
         Ok(())
     }
 
-    /// Try to become the X Server's window manager.
-    /// TODO: link to documentation, or explain it here
-    fn acquire_wm_event_mask(&self) -> xcb::Result<()> {
+    /// Try to become the X Server's window manager. Only one client may select SubstructureRedirect on the root
+    /// window, so this fails if another window manager is running (even one which doesn't use the manager selection).
+    fn acquire_wm_event_mask(&self) -> Result<(), ManagerError> {
         let c = self.conn.send_request_checked(&x::ChangeWindowAttributes {
             window: self.get_root_window()?,
             value_list: &[x::Cw::EventMask(
@@ -194,14 +379,24 @@ impl<'a> WindowManager<'a> {
         match self.conn.check_request(c) {
             Ok(_) => {}
             Err(xcb::ProtocolError::X(x::Error::Access(req), _)) if req.error_code() == 10 => {
-                panic!("Is there an existing WM already?");
+                return Err(ManagerError::AlreadyRunning);
             }
-            _ => unimplemented!(),
+            Err(e) => return Err(e.into()),
         }
 
         Ok(())
     }
 
+    /// Is the given window (or its frame) the focused window?
+    fn is_focused(&self, window: x::Window) -> bool {
+        match self.focused_window {
+            Some(focused) => self
+                .get_frame_and_window(focused)
+                .map_or(focused == window, |(w, _)| w == window),
+            None => false,
+        }
+    }
+
     /// Get the X Server's root window from the default screen.
     fn get_root_window(&self) -> xcb::Result<x::Window> {
         let setup = self.conn.get_setup();
@@ -209,44 +404,4 @@ impl<'a> WindowManager<'a> {
         let root = screen.root();
         Ok(root)
     }
-
-    // TODO: doc
-    // TODO: in the future, will probably have to maintain some internal state and only render diff
-    //  rather than touching every window every single time an event is received
-    fn render(&self) -> xcb::Result<()> {
-        let mut requests = vec![];
-
-        // Iterate through all frames and color the focused one if it exists (the focused window may
-        // be a window that's not framed)
-        for (window, frame) in &self.framed_clients {
-            let is_focused = Some(*window) == self.focused_window || Some(*frame) == self.focused_window;
-            requests.push(self.conn.send_request_checked(&x::ChangeWindowAttributes {
-                window: *frame,
-                value_list: &[x::Cw::BorderPixel(if is_focused { 0xff0000 } else { 0xaaaaaa })],
-            }));
-            if is_focused {
-                requests.push(self.conn.send_request_checked(&x::ConfigureWindow {
-                    window: *frame,
-                    value_list: &[x::ConfigWindow::StackMode(x::StackMode::Above)],
-                }))
-            }
-        }
-
-        for cookie in requests {
-            self.conn.check_request(cookie)?;
-        }
-
-        // If we have a focused window, then tell X to focus it specifically.
-        if let Some(target) = self.focused_window {
-            // If the focused window is a frame, then focus its window
-            let focus = *self.framed_clients.get_by_right(&target).unwrap_or(&target);
-            self.conn.send_and_check_request(&x::SetInputFocus {
-                revert_to: x::InputFocus::PointerRoot,
-                focus,
-                time: x::CURRENT_TIME,
-            })?;
-        }
-
-        Ok(())
-    }
 }
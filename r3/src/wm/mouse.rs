@@ -0,0 +1,122 @@
+use std::time::{Duration, Instant};
+
+use xcb::{x, Xid};
+
+use super::{DragType, WindowManager};
+use crate::config::MouseAction;
+use crate::point::Point;
+use crate::window_geometry::WindowGeometry;
+use crate::{logging, ret_ok_if_none};
+
+/// How often a window being resized with the pointer is resized, unless it tells us when it has repainted.
+/// Clients can't repaint any faster than the display refreshes, so resizing them more often only makes them fall
+/// behind the pointer.
+const DRAG_RESIZE_INTERVAL: Duration = Duration::from_millis(16);
+
+impl<'a> WindowManager<'a> {
+    /// Run the action of a mouse binding. `clicked` is the window and frame that was clicked, if any: actions
+    /// other than commands do nothing without one.
+    pub(super) fn run_mouse_action(
+        &mut self,
+        action: &MouseAction,
+        clicked: Option<(x::Window, x::Window)>,
+        pointer: Point,
+    ) -> xcb::Result<()> {
+        let (window, frame) = match (action, clicked) {
+            (MouseAction::Command(command), _) => {
+                self.queue_command(command.clone());
+                return Ok(());
+            }
+            (_, Some(clicked)) => clicked,
+            (_, None) => return Ok(()),
+        };
+
+        match action {
+            MouseAction::Move => self.start_drag(frame, DragType::Move, pointer)?,
+            MouseAction::Resize => self.start_drag(frame, DragType::Resize, pointer)?,
+            MouseAction::Raise => {
+                self.conn.send_and_check_request(&x::ConfigureWindow {
+                    window: frame,
+                    value_list: &[x::ConfigWindow::StackMode(x::StackMode::Above)],
+                })?;
+                self.rendered.invalidate_stacking();
+            }
+            MouseAction::Close => self.kill_window(window)?,
+            MouseAction::Focus => self.focus_window(Some(window)),
+            MouseAction::Command(_) => {}
+        }
+
+        Ok(())
+    }
+
+    /// Start moving or resizing a frame with the pointer, until the button is released
+    fn start_drag(&mut self, frame: x::Window, drag_type: DragType, drag_start: Point) -> xcb::Result<()> {
+        let drag_start_frame_rect = self.get_window_rect(frame)?;
+        self.drag_start = Some(drag_start);
+        self.drag_start_frame_rect = Some(drag_start_frame_rect);
+
+        // Show a cursor for the kind of drag: resizing happens from the nearest corner
+        let cursor = match drag_type {
+            DragType::Move => Some(self.cursors.moving),
+            DragType::Resize => drag_start_frame_rect
+                .quadrant(&drag_start)
+                .map(|q| self.cursors.resizing(q)),
+        };
+        self.drag_type = Some(drag_type);
+
+        if let Some(cursor) = cursor {
+            let grab = self.conn.wait_for_reply(self.conn.send_request(&x::GrabPointer {
+                owner_events: false,
+                grab_window: frame,
+                event_mask: x::EventMask::BUTTON_RELEASE | x::EventMask::BUTTON_MOTION,
+                pointer_mode: x::GrabMode::Async,
+                keyboard_mode: x::GrabMode::Async,
+                confine_to: Xid::none(),
+                cursor,
+                time: x::CURRENT_TIME,
+            }))?;
+            // Another client may already have the pointer, in which case the button release would never be seen
+            if grab.status() != x::GrabStatus::Success {
+                log::warn!(target: logging::X, "Failed to grab the pointer to drag {:?}: {:?}", frame, grab.status());
+                self.drag_type = None;
+                self.drag_start_frame_rect = None;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Resize a window being dragged. Resizes are throttled to `DRAG_RESIZE_INTERVAL`: until then, only the
+    /// latest geometry is kept, and it's applied by `apply_drag_resize`.
+    pub(super) fn drag_resize(&mut self, window: x::Window, rect: WindowGeometry) -> xcb::Result<()> {
+        self.pending_drag_resize = Some((window, rect));
+        self.resize_dragged_window(false)
+    }
+
+    /// Apply the latest geometry of a window being resized with the pointer, if it's time to. This is called
+    /// each time around the event loop, so a drag's last resize is applied even if the pointer stops moving.
+    pub fn apply_drag_resize(&mut self) -> xcb::Result<()> {
+        let result = self.resize_dragged_window(false);
+        self.recover(result)
+    }
+
+    /// Resize the window being dragged to its latest geometry, unless it hasn't caught up with its last resize
+    /// (and `force` isn't set). Clients which support `_NET_WM_SYNC_REQUEST` are resized as soon as they've
+    /// repainted, and others at most once every `DRAG_RESIZE_INTERVAL`.
+    pub(super) fn resize_dragged_window(&mut self, force: bool) -> xcb::Result<()> {
+        let (window, _) = *ret_ok_if_none!(self.pending_drag_resize.as_ref());
+        let waiting = match self.clients.get(&window).and_then(|c| c.sync.as_ref()) {
+            Some(sync) => sync.is_waiting(),
+            None => self
+                .last_drag_resize
+                .is_some_and(|t| t.elapsed() < DRAG_RESIZE_INTERVAL),
+        };
+        if waiting && !force {
+            return Ok(());
+        }
+
+        let (window, rect) = ret_ok_if_none!(self.pending_drag_resize.take());
+        self.last_drag_resize = Some(Instant::now());
+        self.resize_window(window, rect)
+    }
+}
@@ -0,0 +1,91 @@
+use r3lib::Direction;
+use xcb::x;
+
+use super::WindowManager;
+use crate::ret_ok_if_none;
+use crate::window_geometry::WindowGeometry;
+
+impl<'a> WindowManager<'a> {
+    /// The frame geometry of every managed window on the current workspace
+    fn visible_frame_rects(&self) -> xcb::Result<Vec<(x::Window, WindowGeometry)>> {
+        // Send all the requests up front, rather than waiting for each window's replies in turn
+        let cookies = self
+            .framed_clients
+            .iter()
+            .filter(|(window, _)| {
+                self.clients
+                    .get(window)
+                    .is_some_and(|c| c.workspace == self.current_workspace && !c.scratchpad_hidden)
+            })
+            .map(|(window, frame)| {
+                (
+                    *window,
+                    self.conn.send_request(&x::GetGeometry {
+                        drawable: x::Drawable::Window(*frame),
+                    }),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        cookies
+            .into_iter()
+            .map(|(window, cookie)| {
+                let geo = self.conn.wait_for_reply(cookie)?;
+                let rect = (geo.x(), geo.y(), geo.width(), geo.height(), geo.border_width()).into();
+                Ok((window, rect))
+            })
+            .collect()
+    }
+
+    /// Find the nearest visible window in the given direction from `window`, along with both windows' frame
+    /// geometry. Floating and tiled windows are treated alike.
+    fn window_in_direction(
+        &self,
+        window: x::Window,
+        direction: Direction,
+    ) -> xcb::Result<Option<(x::Window, WindowGeometry, WindowGeometry)>> {
+        let rects = self.visible_frame_rects()?;
+        let rect = match rects.iter().find(|(w, _)| *w == window) {
+            Some((_, rect)) => *rect,
+            None => return Ok(None),
+        };
+
+        Ok(rects
+            .iter()
+            .filter(|(w, _)| *w != window)
+            .filter_map(|(w, r)| rect.distance_in_direction(r, direction).map(|d| (d, *w, *r)))
+            .min_by_key(|(d, _, _)| *d)
+            .map(|(_, w, r)| (w, rect, r)))
+    }
+
+    /// Focus the nearest window in the given direction from the focused window.
+    pub(super) fn focus_direction(&mut self, direction: Direction) -> xcb::Result<()> {
+        let window = ret_ok_if_none!(self.focused_window);
+        if let Some((target, _, _)) = self.window_in_direction(window, direction)? {
+            self.focus_window(Some(target));
+        }
+
+        Ok(())
+    }
+
+    /// Swap the focused window's position and size with the nearest window in the given direction. The focused
+    /// window keeps focus, so repeated swaps move it across the screen.
+    pub(super) fn swap_direction(&mut self, direction: Direction) -> xcb::Result<()> {
+        let window = ret_ok_if_none!(self.focused_window);
+        let (target, rect, target_rect) = ret_ok_if_none!(self.window_in_direction(window, direction)?);
+
+        self.resize_window(window, target_rect)?;
+        self.resize_window(target, rect)?;
+
+        // Being maximized goes with the geometry, so restoring a window returns it to the other's previous spot
+        let unmaximized_rect = self.clients.get(&window).and_then(|c| c.unmaximized_rect);
+        let target_unmaximized_rect = self.clients.get(&target).and_then(|c| c.unmaximized_rect);
+        for (w, unmaximized_rect) in [(window, target_unmaximized_rect), (target, unmaximized_rect)] {
+            if let Some(client) = self.clients.get_mut(&w) {
+                client.unmaximized_rect = unmaximized_rect;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+
+use xcb::{x, Xid};
+
+use super::decorations::is_button_active;
+use super::WindowManager;
+use crate::theme::{Color, ColorSet};
+
+/// What r3 last sent to X about its frames. `render` works out how each frame should look from the window
+/// manager's state, compares that with what was last sent, and only sends requests for what's changed.
+#[derive(Debug, Default)]
+pub struct RenderedState {
+    /// The last state sent for each client's frame
+    frames: HashMap<x::Window, FrameState>,
+    /// The frames that were raised the last time frames were restacked, or `None` if they need restacking
+    stacking: Option<Stacking>,
+    /// The window X's input focus was last given to
+    focus: Option<x::Window>,
+}
+
+#[derive(Debug)]
+struct FrameState {
+    mapped: bool,
+    border: Option<Color>,
+    /// `None` if the title bar hasn't been drawn since its contents were lost
+    title_bar: Option<TitleBarState>,
+}
+
+impl FrameState {
+    /// A newly created frame, which is mapped but hasn't been drawn
+    fn new() -> FrameState {
+        FrameState {
+            mapped: true,
+            border: None,
+            title_bar: None,
+        }
+    }
+}
+
+/// Everything a title bar's appearance depends on
+#[derive(Debug, Clone, PartialEq)]
+struct TitleBarState {
+    colors: ColorSet,
+    title: String,
+    /// Whether each button is hovered, and whether it's active
+    buttons: Vec<(bool, bool)>,
+}
+
+/// The frames which are kept raised: the focused frame, and then the floating frames above it
+#[derive(Debug, PartialEq, Eq)]
+struct Stacking {
+    focused: Option<x::Window>,
+    floating: Vec<x::Window>,
+}
+
+impl RenderedState {
+    /// A frame has been created (and mapped) for a window.
+    pub fn add_frame(&mut self, window: x::Window) {
+        self.frames.insert(window, FrameState::new());
+        self.stacking = None;
+    }
+
+    pub fn remove_frame(&mut self, window: x::Window) {
+        self.frames.remove(&window);
+    }
+
+    /// The contents of a window's title bar were lost (e.g. it was exposed), so it needs to be redrawn.
+    pub fn invalidate_title_bar(&mut self, window: x::Window) {
+        if let Some(frame) = self.frames.get_mut(&window) {
+            frame.title_bar = None;
+        }
+    }
+
+    /// Frames were restacked by something other than `render`, so the raised frames need raising again.
+    pub fn invalidate_stacking(&mut self) {
+        self.stacking = None;
+    }
+
+    pub fn set_focus(&mut self, window: Option<x::Window>) {
+        self.focus = window;
+    }
+}
+
+impl<'a> WindowManager<'a> {
+    /// Push the window manager's state to X, sending only what's changed since the last render. Requests are
+    /// sent unchecked, so rendering doesn't wait on the X server: if one fails (e.g. its window was just
+    /// destroyed) the error arrives with the next events, and is handled by `on_x_error`.
+    pub(super) fn render(&mut self) -> xcb::Result<()> {
+        let mut focused_frame = None;
+        let mut floating_frames = vec![];
+        let mut restack = false;
+        let framed_clients = self.framed_clients.iter().map(|(w, f)| (*w, *f)).collect::<Vec<_>>();
+        for (window, frame) in framed_clients {
+            let title_bar = match self.title_bar_state(window) {
+                Some(title_bar) => title_bar,
+                None => continue,
+            };
+            let mapped = self.is_shown(window);
+            let border = self.frame_colors(window).border;
+            if self.is_focused(window) {
+                focused_frame = Some(frame);
+            }
+            if self.clients.get(&window).is_some_and(|c| c.floating) {
+                floating_frames.push(frame);
+            }
+
+            let rendered = self.rendered.frames.entry(window).or_insert_with(FrameState::new);
+            if rendered.mapped != mapped {
+                rendered.mapped = mapped;
+                match mapped {
+                    true => {
+                        self.conn.send_request(&x::MapWindow { window: frame });
+                        rendered.title_bar = None;
+                        restack = true;
+                    }
+                    false => {
+                        // We'll receive an UnmapNotify for the frame, which would otherwise look like the frame
+                        // was destroyed
+                        if let Some(client) = self.clients.get_mut(&window) {
+                            client.ignore_unmaps += 1;
+                        }
+                        self.conn.send_request(&x::UnmapWindow { window: frame });
+                    }
+                }
+            }
+
+            if rendered.border != Some(border) {
+                rendered.border = Some(border);
+                self.conn.send_request(&x::ChangeWindowAttributes {
+                    window: frame,
+                    value_list: &[x::Cw::BorderPixel(border.0)],
+                });
+            }
+
+            // Hidden frames are redrawn when they're shown again
+            if mapped && rendered.title_bar.as_ref() != Some(&title_bar) {
+                rendered.title_bar = Some(title_bar);
+                self.draw_title_bar(window)?;
+            }
+        }
+
+        floating_frames.sort_by_key(|frame| frame.resource_id());
+        let stacking = Stacking {
+            focused: focused_frame,
+            floating: floating_frames,
+        };
+        if restack || self.rendered.stacking.as_ref() != Some(&stacking) {
+            self.restack(&stacking)?;
+            self.rendered.stacking = Some(stacking);
+        }
+
+        // If focus changed, then tell X to focus the window specifically.
+        if self.focused_window != self.rendered.focus {
+            self.apply_input_focus()?;
+        }
+
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    /// Raise the focused window, and then keep floating windows above all other windows. Floating windows
+    /// are raised in their current stacking order (bottom to top) so they don't shuffle amongst themselves.
+    fn restack(&self, stacking: &Stacking) -> xcb::Result<()> {
+        let mut raised = vec![];
+        if !stacking.floating.is_empty() {
+            let query_tree = self.conn.wait_for_reply(self.conn.send_request(&x::QueryTree {
+                window: self.get_root_window()?,
+            }))?;
+            raised.extend(
+                query_tree
+                    .children()
+                    .iter()
+                    .filter(|w| stacking.floating.contains(w) && Some(**w) != stacking.focused),
+            );
+        }
+        if let Some(frame) = stacking.focused {
+            match stacking.floating.contains(&frame) {
+                true => raised.push(frame),
+                false => raised.insert(0, frame),
+            }
+        }
+        for frame in raised {
+            self.conn.send_request(&x::ConfigureWindow {
+                window: frame,
+                value_list: &[x::ConfigWindow::StackMode(x::StackMode::Above)],
+            });
+        }
+
+        Ok(())
+    }
+
+    fn title_bar_state(&self, window: x::Window) -> Option<TitleBarState> {
+        let client = self.clients.get(&window)?;
+        let buttons = client
+            .buttons
+            .iter()
+            .filter_map(|button| {
+                let (_, kind) = self.title_bar_buttons.get(button)?;
+                Some((self.hovered_button == Some(*button), is_button_active(client, *kind)))
+            })
+            .collect();
+
+        Some(TitleBarState {
+            colors: self.frame_colors(window).clone(),
+            title: client.title.clone(),
+            buttons,
+        })
+    }
+}
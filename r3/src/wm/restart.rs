@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use xcb::{x, Xid};
+
+use super::workspaces::WORKSPACE_COUNT;
+use super::WindowManager;
+use crate::window_geometry::WindowGeometry;
+
+/// The state r3 keeps about its windows, saved when restarting so the new process can pick up where the old one
+/// left off. Windows are identified by their X ids, which don't change across a restart.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SavedState {
+    current_workspace: usize,
+    focused_window: Option<u32>,
+    /// The focus history of each workspace, from least to most recently focused
+    focus_history: Vec<Vec<u32>>,
+    /// Windows in the scratchpad, in the order they were added to it
+    scratchpad: Vec<u32>,
+    clients: Vec<SavedClient>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedClient {
+    id: u32,
+    /// The frame's geometry
+    rect: WindowGeometry,
+    unmaximized_rect: Option<WindowGeometry>,
+    workspace: usize,
+    floating: bool,
+    scratchpad_hidden: bool,
+    no_border: bool,
+    sticky: bool,
+}
+
+impl<'a> WindowManager<'a> {
+    /// Save the state of every managed window, to be restored by `restore_state` after a restart
+    pub fn save_state(&self) -> xcb::Result<SavedState> {
+        let cookies = self
+            .framed_clients
+            .iter()
+            .map(|(window, frame)| {
+                let cookie = self.conn.send_request(&x::GetGeometry {
+                    drawable: x::Drawable::Window(*frame),
+                });
+                (*window, cookie)
+            })
+            .collect::<Vec<_>>();
+
+        let mut clients = vec![];
+        for (window, cookie) in cookies {
+            let geo = self.conn.wait_for_reply(cookie)?;
+            let client = match self.clients.get(&window) {
+                Some(client) => client,
+                None => continue,
+            };
+            clients.push(SavedClient {
+                id: window.resource_id(),
+                rect: (geo.x(), geo.y(), geo.width(), geo.height(), geo.border_width()).into(),
+                unmaximized_rect: client.unmaximized_rect,
+                workspace: client.workspace,
+                floating: client.floating,
+                scratchpad_hidden: client.scratchpad_hidden,
+                no_border: client.no_border,
+                sticky: client.sticky,
+            });
+        }
+
+        Ok(SavedState {
+            current_workspace: self.current_workspace,
+            focused_window: self.focused_window.map(|w| w.resource_id()),
+            focus_history: self
+                .workspaces
+                .iter()
+                .map(|w| w.focus_history.iter_recent().rev().map(|w| w.resource_id()).collect())
+                .collect(),
+            scratchpad: self.scratchpad.iter().map(|w| w.resource_id()).collect(),
+            clients,
+        })
+    }
+
+    /// Restore the state saved before a restart. This should be called after becoming the window manager, once the
+    /// existing windows have been framed: windows which weren't saved are left on the current workspace.
+    pub fn restore_state(&mut self, state: SavedState) -> xcb::Result<()> {
+        let result = self.apply_state(state).and_then(|_| self.render());
+        self.recover(result)
+    }
+
+    fn apply_state(&mut self, state: SavedState) -> xcb::Result<()> {
+        let windows = self
+            .framed_clients
+            .left_values()
+            .map(|w| (w.resource_id(), *w))
+            .collect::<HashMap<_, _>>();
+        let saved = state
+            .clients
+            .iter()
+            .filter_map(|c| windows.get(&c.id).map(|w| (*w, c)))
+            .collect::<HashMap<_, _>>();
+
+        self.current_workspace = state.current_workspace.min(WORKSPACE_COUNT - 1);
+        for window in windows.values().copied() {
+            let client = match self.clients.get_mut(&window) {
+                Some(client) => client,
+                None => continue,
+            };
+            let saved = match saved.get(&window) {
+                Some(saved) => saved,
+                None => {
+                    client.workspace = self.current_workspace;
+                    continue;
+                }
+            };
+
+            client.unmaximized_rect = saved.unmaximized_rect;
+            client.workspace = saved.workspace.min(WORKSPACE_COUNT - 1);
+            client.floating = saved.floating;
+            client.scratchpad_hidden = saved.scratchpad_hidden;
+            client.no_border = saved.no_border;
+            client.sticky = saved.sticky;
+
+            self.resize_window(window, saved.rect)?;
+            if let Some(frame) = self.framed_clients.get_by_left(&window).filter(|_| saved.no_border) {
+                self.conn.send_and_check_request(&x::ConfigureWindow {
+                    window: *frame,
+                    value_list: &[x::ConfigWindow::BorderWidth(0)],
+                })?;
+            }
+        }
+
+        for (workspace, history) in self.workspaces.iter_mut().zip(&state.focus_history) {
+            workspace.focus_history = Default::default();
+            for window in history.iter().filter_map(|id| windows.get(id)) {
+                workspace.focus_history.push(*window);
+            }
+        }
+        self.scratchpad = state
+            .scratchpad
+            .iter()
+            .filter_map(|id| windows.get(id).copied())
+            .collect();
+
+        match state.focused_window.and_then(|id| windows.get(&id)) {
+            Some(window) => self.focus_window(Some(*window)),
+            None => self.focus_previous(),
+        }
+
+        Ok(())
+    }
+}
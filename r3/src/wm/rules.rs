@@ -0,0 +1,129 @@
+use xcb::x;
+
+use super::WindowManager;
+use crate::rules::WindowProperties;
+
+/// Prefix of the `_NET_WM_WINDOW_TYPE` atoms, which is stripped before matching rules
+const WINDOW_TYPE_PREFIX: &str = "_NET_WM_WINDOW_TYPE_";
+
+impl<'a> WindowManager<'a> {
+    /// Read the properties of a window that rules can match on
+    fn get_window_properties(&self, window: x::Window) -> xcb::Result<WindowProperties> {
+        let get_property = |property, r#type| {
+            self.conn.send_request(&x::GetProperty {
+                delete: false,
+                window,
+                property,
+                r#type,
+                long_offset: 0,
+                long_length: u32::MAX,
+            })
+        };
+        let wm_class = get_property(x::ATOM_WM_CLASS, x::ATOM_STRING);
+        let role = get_property(self.atoms.wm_window_role, x::ATOM_STRING);
+        let window_type = get_property(self.atoms.net_wm_window_type, x::ATOM_ATOM);
+
+        // WM_CLASS is two null-terminated strings: the instance, and then the class
+        let wm_class = self.conn.wait_for_reply(wm_class)?;
+        let mut wm_class = wm_class
+            .value::<u8>()
+            .split(|b| *b == 0)
+            .map(|s| String::from_utf8_lossy(s).into_owned());
+        let instance = wm_class.next().unwrap_or_default();
+        let class = wm_class.next().unwrap_or_default();
+
+        let role = String::from_utf8_lossy(self.conn.wait_for_reply(role)?.value()).into_owned();
+
+        let type_names = self
+            .conn
+            .wait_for_reply(window_type)?
+            .value::<x::Atom>()
+            .iter()
+            .map(|atom| self.conn.send_request(&x::GetAtomName { atom: *atom }))
+            .collect::<Vec<_>>();
+        let mut window_types = vec![];
+        for cookie in type_names {
+            let name = self.conn.wait_for_reply(cookie)?;
+            let name = name.name().to_utf8();
+            if let Some(window_type) = name.strip_prefix(WINDOW_TYPE_PREFIX) {
+                window_types.push(window_type.to_lowercase());
+            }
+        }
+
+        Ok(WindowProperties {
+            class,
+            instance,
+            title: self.get_window_title(window)?,
+            role,
+            window_types,
+        })
+    }
+
+    /// Apply the configured rules to a newly framed window. Returns whether the window should be focused.
+    pub(super) fn apply_rules(&mut self, window: x::Window) -> xcb::Result<bool> {
+        if self.config.rules.is_empty() {
+            return Ok(true);
+        }
+
+        let properties = self.get_window_properties(window)?;
+        let rules = self
+            .config
+            .rules
+            .iter()
+            .filter(|rule| rule.matches(&properties))
+            .collect::<Vec<_>>();
+
+        let mut focus = true;
+        let mut workspace = None;
+        let mut commands = vec![];
+        let frame = match self.framed_clients.get_by_left(&window) {
+            Some(frame) => *frame,
+            None => return Ok(true),
+        };
+        let mut rect = self.get_window_rect(frame)?;
+        let mut moved = false;
+        for rule in rules {
+            let client = match self.clients.get_mut(&window) {
+                Some(client) => client,
+                None => return Ok(false),
+            };
+
+            if let Some(floating) = rule.floating {
+                client.floating = floating;
+            }
+            if let Some((x, y)) = rule.position {
+                let gap = self.theme.gap as i16;
+                (rect.x, rect.y) = (x.saturating_add(gap), y.saturating_add(gap));
+                moved = true;
+            }
+            if let Some((w, h)) = rule.size {
+                (rect.w, rect.h) = (w, h.saturating_add(self.theme.title_bar_height));
+                moved = true;
+            }
+            client.no_border |= rule.no_border;
+            client.sticky |= rule.sticky;
+            focus &= !rule.skip_focus;
+            workspace = rule.workspace.or(workspace);
+            commands.extend(rule.command.clone());
+        }
+
+        if moved {
+            self.resize_window(window, rect)?;
+        }
+        if self.clients.get(&window).is_some_and(|c| c.no_border) {
+            self.conn.send_and_check_request(&x::ConfigureWindow {
+                window: frame,
+                value_list: &[x::ConfigWindow::BorderWidth(0)],
+            })?;
+        }
+        if let Some(workspace) = workspace {
+            self.move_to_workspace(window, workspace)?;
+        }
+
+        for command in commands {
+            self.queue_command(command);
+        }
+
+        Ok(focus)
+    }
+}
@@ -0,0 +1,89 @@
+use xcb::x;
+
+use super::WindowManager;
+use crate::ret_ok_if_none;
+
+impl<'a> WindowManager<'a> {
+    /// Is the window's frame currently mapped on the shown workspace?
+    pub(super) fn is_shown(&self, window: x::Window) -> bool {
+        self.clients
+            .get(&window)
+            .is_some_and(|c| c.workspace == self.current_workspace && !c.scratchpad_hidden)
+    }
+
+    /// Add a window to the scratchpad and hide it. It stays in the scratchpad until it's closed.
+    pub(super) fn move_to_scratchpad(&mut self, window: x::Window) -> xcb::Result<()> {
+        if !self.clients.contains_key(&window) {
+            return Ok(());
+        }
+
+        if !self.scratchpad.contains(&window) {
+            self.scratchpad.push(window);
+        }
+
+        self.hide_in_scratchpad(window)
+    }
+
+    fn hide_in_scratchpad(&mut self, window: x::Window) -> xcb::Result<()> {
+        let client = ret_ok_if_none!(self.clients.get_mut(&window));
+        client.scratchpad_hidden = true;
+        let workspace = client.workspace;
+        self.workspaces[workspace].focus_history.remove(window);
+        if self.focused_window == Some(window) {
+            self.focus_previous();
+        }
+
+        Ok(())
+    }
+
+    /// Toggle a scratchpad window whose title contains `criteria` (or any, if there are no criteria): if one is
+    /// focused it's hidden, otherwise one is brought to the current workspace, floating in the middle of the screen.
+    pub(super) fn scratchpad_show(&mut self, criteria: Option<&str>) -> xcb::Result<()> {
+        let matches = self
+            .scratchpad
+            .iter()
+            .copied()
+            .filter(|w| {
+                self.clients
+                    .get(w)
+                    .is_some_and(|c| criteria.is_none_or(|text| c.title.contains(text)))
+            })
+            .collect::<Vec<_>>();
+
+        if let Some(window) = matches.iter().find(|w| self.is_focused(**w)) {
+            return self.hide_in_scratchpad(*window);
+        }
+
+        match matches.iter().find(|w| !self.is_shown(**w)) {
+            Some(window) => self.show_from_scratchpad(*window),
+            None => {
+                // Everything that matches is already shown, so just focus it
+                if let Some(window) = matches.first() {
+                    self.focus_window(Some(*window));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    pub(super) fn show_from_scratchpad(&mut self, window: x::Window) -> xcb::Result<()> {
+        let frame = *ret_ok_if_none!(self.framed_clients.get_by_left(&window));
+        let client = ret_ok_if_none!(self.clients.get_mut(&window));
+        let previous = client.workspace;
+        client.workspace = self.current_workspace;
+        client.scratchpad_hidden = false;
+        client.floating = true;
+        self.workspaces[previous].focus_history.remove(window);
+
+        // Centre the frame on the screen, keeping its size
+        let rect = self.get_window_rect(frame)?;
+        let root_rect = self.get_window_rect(self.get_root_window()?)?;
+        let x = (root_rect.w as i16 - rect.full_width() as i16) / 2;
+        let y = (root_rect.h as i16 - rect.full_height() as i16) / 2;
+        self.resize_window(window, (x, y, rect.w, rect.h).into())?;
+
+        self.focus_window(Some(window));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,271 @@
+use std::error::Error;
+use std::time::{Duration, Instant};
+use std::{fmt, thread};
+
+use r3lib::R3Command;
+use xcb::{x, Xid};
+
+use super::WindowManager;
+
+/// How long to wait for the previous window manager to exit when replacing it
+const REPLACE_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// The `WM_S<n>` manager selection, which r3 owns while it's the window manager for screen `n`.
+/// See: https://tronche.com/gui/x/icccm/sec-2.html#s-2.8
+pub struct ManagerSelection {
+    /// The `WM_S<n>` atom
+    atom: x::Atom,
+    /// The (never mapped) window which owns the selection
+    window: x::Window,
+}
+
+/// Why r3 couldn't become the window manager
+#[derive(Debug)]
+pub enum ManagerError {
+    /// Another window manager is running, and we weren't asked to replace it
+    AlreadyRunning,
+    /// We asked the running window manager to exit, but it didn't
+    ReplaceTimeout,
+    /// Another client took the selection from us while we were acquiring it
+    SelectionLost,
+    X(xcb::Error),
+}
+
+impl fmt::Display for ManagerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ManagerError::AlreadyRunning => {
+                write!(f, "another window manager is running (use --replace to replace it)")
+            }
+            ManagerError::ReplaceTimeout => write!(f, "the running window manager didn't exit when asked to"),
+            ManagerError::SelectionLost => write!(f, "another client took the window manager selection"),
+            ManagerError::X(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for ManagerError {}
+
+impl From<xcb::Error> for ManagerError {
+    fn from(e: xcb::Error) -> ManagerError {
+        ManagerError::X(e)
+    }
+}
+
+impl From<xcb::ProtocolError> for ManagerError {
+    fn from(e: xcb::ProtocolError) -> ManagerError {
+        ManagerError::X(e.into())
+    }
+}
+
+impl<'a> WindowManager<'a> {
+    /// Take ownership of the `WM_S<n>` selection, which is how ICCCM-compliant window managers coordinate. If
+    /// another window manager owns it and `replace` is set, it's asked to exit (by taking the selection from it),
+    /// and we wait until it has.
+    pub(super) fn acquire_manager_selection(&mut self, replace: bool) -> Result<(), ManagerError> {
+        let root = self.get_root_window()?;
+        let name = format!("WM_S{}", self.default_screen);
+        let atom = self.conn.wait_for_reply(self.conn.send_request(&x::InternAtom {
+            only_if_exists: false,
+            name: name.as_bytes(),
+        }))?;
+        let atom = atom.atom();
+
+        let owner = self
+            .conn
+            .wait_for_reply(self.conn.send_request(&x::GetSelectionOwner { selection: atom }))?;
+        let owner = owner.owner();
+        if !owner.is_none() && !replace {
+            return Err(ManagerError::AlreadyRunning);
+        }
+
+        // Watch for the previous owner's window to be destroyed, which is how it tells us it's exited. If it's
+        // already gone, there's nothing to wait for.
+        let previous_owner = match owner.is_none() {
+            true => None,
+            false => self
+                .conn
+                .send_and_check_request(&x::ChangeWindowAttributes {
+                    window: owner,
+                    value_list: &[x::Cw::EventMask(x::EventMask::STRUCTURE_NOTIFY)],
+                })
+                .ok()
+                .map(|_| owner),
+        };
+
+        // Create a window to own the selection. It's also used to get a timestamp, which the selection protocol
+        // requires (rather than CurrentTime), from the PropertyNotify of an empty property change.
+        let window = self.conn.generate_id();
+        self.conn.send_and_check_request(&x::CreateWindow {
+            depth: x::COPY_FROM_PARENT as u8,
+            wid: window,
+            parent: root,
+            x: -1,
+            y: -1,
+            width: 1,
+            height: 1,
+            border_width: 0,
+            class: x::WindowClass::InputOnly,
+            visual: x::COPY_FROM_PARENT,
+            value_list: &[
+                x::Cw::OverrideRedirect(true),
+                x::Cw::EventMask(x::EventMask::PROPERTY_CHANGE),
+            ],
+        })?;
+        self.conn.send_and_check_request(&x::ChangeProperty {
+            mode: x::PropMode::Append,
+            window,
+            property: self.atoms.r3_pid,
+            r#type: x::ATOM_STRING,
+            data: &[] as &[u8],
+        })?;
+        let time = loop {
+            if let xcb::Event::X(x::Event::PropertyNotify(ev)) = self.conn.wait_for_event()? {
+                if ev.window() == window {
+                    break ev.time();
+                }
+            }
+        };
+
+        self.conn.send_and_check_request(&x::SetSelectionOwner {
+            owner: window,
+            selection: atom,
+            time,
+        })?;
+        let new_owner = self
+            .conn
+            .wait_for_reply(self.conn.send_request(&x::GetSelectionOwner { selection: atom }))?;
+        if new_owner.owner() != window {
+            return Err(ManagerError::SelectionLost);
+        }
+
+        if let Some(owner) = previous_owner {
+            self.wait_for_destroy(owner)?;
+        }
+
+        // Let other clients know there's a new window manager
+        self.conn.send_and_check_request(&x::SendEvent {
+            propagate: false,
+            destination: x::SendEventDest::Window(root),
+            event_mask: x::EventMask::STRUCTURE_NOTIFY,
+            event: &x::ClientMessageEvent::new(
+                root,
+                self.atoms.manager,
+                x::ClientMessageData::Data32([time, atom.resource_id(), window.resource_id(), 0, 0]),
+            ),
+        })?;
+
+        self.manager_selection = Some(ManagerSelection { atom, window });
+        Ok(())
+    }
+
+    /// Wait for the previous window manager's selection window to be destroyed
+    fn wait_for_destroy(&self, window: x::Window) -> Result<(), ManagerError> {
+        let start = Instant::now();
+        while start.elapsed() < REPLACE_TIMEOUT {
+            match self.conn.poll_for_event()? {
+                Some(xcb::Event::X(x::Event::DestroyNotify(ev))) if ev.window() == window => return Ok(()),
+                Some(_) => {}
+                None => thread::sleep(Duration::from_millis(10)),
+            }
+        }
+
+        Err(ManagerError::ReplaceTimeout)
+    }
+
+    /// Another window manager has taken the selection from us, so stop managing windows and exit.
+    pub(super) fn on_selection_clear(&mut self, ev: x::SelectionClearEvent) -> xcb::Result<()> {
+        match &self.manager_selection {
+            Some(selection) if selection.atom == ev.selection() && selection.window == ev.owner() => {}
+            _ => return Ok(()),
+        };
+
+        log::info!("Another window manager is replacing r3");
+        self.release()?;
+        self.queue_command(R3Command::Exit);
+        Ok(())
+    }
+
+    /// Stop being the window manager, so another can take over. Everything we hold on the root window is released
+    /// before our selection window is destroyed, since that tells the next window manager it can start.
+    pub fn release(&mut self) -> xcb::Result<()> {
+        let root = self.get_root_window()?;
+        self.conn.send_request(&x::ChangeWindowAttributes {
+            window: root,
+            value_list: &[x::Cw::EventMask(x::EventMask::NO_EVENT)],
+        });
+        self.conn.send_request(&x::UngrabKey {
+            key: x::GRAB_ANY,
+            grab_window: root,
+            modifiers: x::ModMask::ANY,
+        });
+        self.conn.send_request(&x::UngrabButton {
+            button: x::ButtonIndex::Any,
+            grab_window: root,
+            modifiers: x::ModMask::ANY,
+        });
+        // The selection window is destroyed even if releasing the windows failed, so the next window manager can
+        // still start
+        let released = self.release_windows();
+        // Nothing we rendered is still shown, so it all needs sending again if we manage the windows again
+        self.rendered = Default::default();
+        match self.manager_selection.take() {
+            Some(selection) => self.conn.send_and_check_request(&x::DestroyWindow {
+                window: selection.window,
+            })?,
+            // Make sure everything above has been done before returning
+            None => self.conn.send_and_check_request(&x::NoOperation {})?,
+        }
+
+        released
+    }
+
+    /// Put every client window back on the root window where it's currently shown, so the next window manager can
+    /// adopt it. Windows on other workspaces (or in the scratchpad) are shown too, so they aren't lost.
+    fn release_windows(&mut self) -> xcb::Result<()> {
+        let root = self.get_root_window()?;
+        let windows = self
+            .framed_clients
+            .iter()
+            .map(|(window, frame)| {
+                let cookie = self.conn.send_request(&x::TranslateCoordinates {
+                    src_window: *window,
+                    dst_window: root,
+                    src_x: 0,
+                    src_y: 0,
+                });
+                (*window, *frame, cookie)
+            })
+            .collect::<Vec<_>>();
+
+        for (window, frame, cookie) in windows {
+            // If the window has been destroyed there's nothing to give back, but its frame still needs destroying
+            if let Ok(position) = self.conn.wait_for_reply(cookie) {
+                self.conn.send_request(&x::UngrabButton {
+                    button: x::ButtonIndex::Any,
+                    grab_window: window,
+                    modifiers: x::ModMask::ANY,
+                });
+                self.conn.send_request(&x::ReparentWindow {
+                    window,
+                    parent: root,
+                    x: position.dst_x(),
+                    y: position.dst_y(),
+                });
+                self.conn.send_request(&x::MapWindow { window });
+                self.conn.send_request(&x::ChangeSaveSet {
+                    window,
+                    mode: x::SetMode::Delete,
+                });
+            }
+            self.conn.send_request(&x::DestroyWindow { window: frame });
+            self.framed_clients.remove_by_left(&window);
+            if let Some(sync) = self.clients.remove(&window).and_then(|c| c.sync) {
+                self.free_sync_state(&sync);
+            }
+            self.rendered.remove_frame(window);
+        }
+
+        Ok(())
+    }
+}
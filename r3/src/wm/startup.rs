@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use xcb::x;
+
+use super::WindowManager;
+use crate::ret_ok_if_none;
+
+/// Launches which haven't been completed (with a "remove" message) are forgotten after this long
+const SEQUENCE_TIMEOUT: Duration = Duration::from_secs(60);
+/// Messages are split into chunks of 20 bytes, so this is plenty for any reasonable message
+const MAX_MESSAGE_LEN: usize = 4096;
+
+/// A launch that's in progress
+struct Sequence {
+    /// The workspace its windows should be placed on
+    workspace: usize,
+    started: Instant,
+}
+
+/// State for the freedesktop.org startup-notification protocol, which lets windows appear on the workspace that was
+/// shown when they were launched. See: https://specifications.freedesktop.org/startup-notification-spec/
+#[derive(Default)]
+pub struct StartupNotifications {
+    /// Messages are sent in chunks, so this contains the partial messages received so far, by sending window
+    partial: HashMap<x::Window, Vec<u8>>,
+    /// Launches in progress, by startup id
+    sequences: HashMap<String, Sequence>,
+}
+
+/// Parse a message such as `new: ID="foo" NAME="Foo Bar" DESKTOP=1` into its type and keys
+fn parse_message(message: &str) -> Option<(&str, HashMap<String, String>)> {
+    let (kind, rest) = message.split_once(':')?;
+
+    // Values may be quoted, and may contain backslash-escaped characters
+    let mut keys = HashMap::new();
+    let mut chars = rest.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let key = chars.by_ref().take_while(|c| *c != '=').collect::<String>();
+        if key.is_empty() {
+            break;
+        }
+
+        let mut value = String::new();
+        let mut quoted = false;
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => quoted = !quoted,
+                '\\' => value.extend(chars.next()),
+                c if c.is_whitespace() && !quoted => break,
+                c => value.push(c),
+            }
+        }
+        keys.insert(key.trim().to_string(), value);
+    }
+
+    Some((kind, keys))
+}
+
+impl<'a> WindowManager<'a> {
+    /// Handle a chunk of a `_NET_STARTUP_INFO_BEGIN` (`begin` is set) or `_NET_STARTUP_INFO` message
+    pub(super) fn on_startup_info(&mut self, window: x::Window, data: &[u8; 20], begin: bool) {
+        let startup = &mut self.startup;
+        if begin {
+            startup.partial.insert(window, vec![]);
+        }
+
+        // Continuations for messages we didn't see the start of are ignored
+        let message = match startup.partial.get_mut(&window) {
+            Some(message) => message,
+            None => return,
+        };
+
+        // The message is complete once it's null-terminated
+        match data.iter().position(|b| *b == 0) {
+            Some(end) => message.extend_from_slice(&data[..end]),
+            None => {
+                message.extend_from_slice(data);
+                if message.len() > MAX_MESSAGE_LEN {
+                    startup.partial.remove(&window);
+                }
+                return;
+            }
+        }
+
+        let message = startup.partial.remove(&window).unwrap_or_default();
+        let message = String::from_utf8_lossy(&message);
+        let (kind, mut keys) = match parse_message(&message) {
+            Some(parsed) => parsed,
+            None => return,
+        };
+        let id = match keys.remove("ID") {
+            Some(id) => id,
+            None => return,
+        };
+
+        startup.sequences.retain(|_, s| s.started.elapsed() < SEQUENCE_TIMEOUT);
+        match kind {
+            "new" => {
+                // The launcher may ask for a specific workspace, otherwise use the one that's shown right now
+                let workspace = keys
+                    .get("DESKTOP")
+                    .and_then(|d| d.parse().ok())
+                    .unwrap_or(self.current_workspace);
+                startup.sequences.insert(
+                    id,
+                    Sequence {
+                        workspace,
+                        started: Instant::now(),
+                    },
+                );
+            }
+            "remove" => {
+                startup.sequences.remove(&id);
+            }
+            _ => {}
+        }
+    }
+
+    /// Read the startup id of a window which is being mapped, and move it to the workspace it was launched on
+    pub(super) fn place_launched_window(&mut self, window: x::Window) -> xcb::Result<()> {
+        if self.startup.sequences.is_empty() {
+            return Ok(());
+        }
+
+        let startup_id = self.conn.wait_for_reply(self.conn.send_request(&x::GetProperty {
+            delete: false,
+            window,
+            property: self.atoms.net_startup_id,
+            r#type: self.atoms.utf8_string,
+            long_offset: 0,
+            long_length: u32::MAX,
+        }))?;
+        let startup_id = String::from_utf8_lossy(startup_id.value());
+        let sequence = ret_ok_if_none!(self.startup.sequences.get(startup_id.as_ref()));
+
+        self.move_to_workspace(window, sequence.workspace)
+    }
+}
@@ -0,0 +1,176 @@
+use std::time::{Duration, Instant};
+
+use xcb::{sync, x, Xid, XidNew};
+
+use super::WindowManager;
+use crate::{logging, ret_ok_if_none};
+
+/// How long to wait for a client to repaint after being resized, before resizing it again anyway
+const SYNC_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// State for a client which supports the EWMH `_NET_WM_SYNC_REQUEST` protocol. Before the client is resized, it's
+/// sent a new value, and it sets its XSync counter to that value once it has repainted at the new size. An alarm
+/// on the counter tells us when that's happened, so the next size isn't sent before the client has caught up.
+#[derive(Debug)]
+pub struct SyncState {
+    /// The client's `_NET_WM_SYNC_REQUEST_COUNTER`
+    counter: sync::Counter,
+    /// The alarm which triggers when the counter reaches `value`, created with the first sync request
+    alarm: Option<sync::Alarm>,
+    /// The value last sent to the client
+    value: u64,
+    /// When the client was last sent a sync request, if it hasn't finished repainting since
+    requested: Option<Instant>,
+    /// The size the client was last resized to, since it only repaints when its size changes
+    size: Option<(u16, u16)>,
+}
+
+impl SyncState {
+    /// Whether the client is still repainting after a resize. Clients which take too long are given up on.
+    pub fn is_waiting(&self) -> bool {
+        self.requested.is_some_and(|t| t.elapsed() < SYNC_TIMEOUT)
+    }
+}
+
+/// Set up the XSync extension, if the X server has it. Returns whether it can be used.
+pub(super) fn init_sync(conn: &xcb::Connection) -> bool {
+    if !conn.active_extensions().any(|e| e == xcb::Extension::Sync) {
+        return false;
+    }
+
+    let cookie = conn.send_request(&sync::Initialize {
+        desired_major_version: 3,
+        desired_minor_version: 1,
+    });
+    conn.wait_for_reply(cookie).is_ok()
+}
+
+impl<'a> WindowManager<'a> {
+    /// Request a window's `_NET_WM_SYNC_REQUEST_COUNTER`, to be passed to `sync_state`.
+    pub(super) fn request_sync_counter(&self, window: x::Window) -> x::GetPropertyCookie {
+        self.conn.send_request(&x::GetProperty {
+            delete: false,
+            window,
+            property: self.atoms.net_wm_sync_request_counter,
+            r#type: x::ATOM_CARDINAL,
+            long_offset: 0,
+            long_length: 1,
+        })
+    }
+
+    /// The sync state for a window, if it supports `_NET_WM_SYNC_REQUEST` (listed in `protocols`) and has a counter.
+    /// Sync requests continue from the counter's current value, since the client may not have started it at 0.
+    pub(super) fn sync_state(
+        &self,
+        protocols: &[x::Atom],
+        counter: &x::GetPropertyReply,
+    ) -> xcb::Result<Option<SyncState>> {
+        if !self.sync_available || !protocols.contains(&self.atoms.net_wm_sync_request) {
+            return Ok(None);
+        }
+
+        let counter = match counter.value::<u32>() {
+            [id, ..] if *id != 0 => *id,
+            _ => return Ok(None),
+        };
+        // SAFETY: an XID is only a number, and if the client gave us a bad one, using it fails with an X error
+        let counter = unsafe { sync::Counter::new(counter) };
+        let value = match self
+            .conn
+            .wait_for_reply(self.conn.send_request(&sync::QueryCounter { counter }))
+        {
+            Ok(reply) => reply.counter_value(),
+            Err(xcb::Error::Protocol(e)) => {
+                log::debug!(target: logging::LAYOUT, "Ignoring invalid sync counter: {:?}", e);
+                return Ok(None);
+            }
+            Err(e) => return Err(e),
+        };
+
+        Ok(Some(SyncState {
+            counter,
+            alarm: None,
+            value: (u64::from(value.hi as u32) << 32) | u64::from(value.lo),
+            requested: None,
+            size: None,
+        }))
+    }
+
+    /// Tell a client that it's about to be resized to `size`, so it updates its sync counter once it has
+    /// repainted. This does nothing if the client doesn't support `_NET_WM_SYNC_REQUEST`, or its size isn't
+    /// changing.
+    pub(super) fn send_sync_request(&mut self, window: x::Window, size: (u16, u16)) {
+        let sync = match self.clients.get_mut(&window).and_then(|c| c.sync.as_mut()) {
+            Some(sync) if sync.size != Some(size) => sync,
+            _ => return,
+        };
+
+        sync.size = Some(size);
+        sync.value += 1;
+        sync.requested = Some(Instant::now());
+        let value = sync::Int64 {
+            hi: (sync.value >> 32) as i32,
+            lo: sync.value as u32,
+        };
+
+        // Set the alarm for the new value before the client can reach it
+        match sync.alarm {
+            Some(alarm) => {
+                self.conn.send_request(&sync::ChangeAlarm {
+                    id: alarm,
+                    value_list: &[sync::Ca::Value(value)],
+                });
+            }
+            None => {
+                let alarm = self.conn.generate_id();
+                self.conn.send_request(&sync::CreateAlarm {
+                    id: alarm,
+                    value_list: &[
+                        sync::Ca::Counter(sync.counter),
+                        sync::Ca::ValueType(sync::Valuetype::Absolute),
+                        sync::Ca::Value(value),
+                        sync::Ca::TestType(sync::Testtype::PositiveComparison),
+                        sync::Ca::Delta(sync::Int64 { hi: 0, lo: 0 }),
+                        sync::Ca::Events(1),
+                    ],
+                });
+                sync.alarm = Some(alarm);
+            }
+        }
+
+        let data = x::ClientMessageData::Data32([
+            self.atoms.net_wm_sync_request.resource_id(),
+            x::CURRENT_TIME,
+            value.lo,
+            value.hi as u32,
+            0,
+        ]);
+        self.conn.send_request(&x::SendEvent {
+            propagate: false,
+            destination: x::SendEventDest::Window(window),
+            event_mask: x::EventMask::NO_EVENT,
+            event: &x::ClientMessageEvent::new(window, self.atoms.wm_protocols, data),
+        });
+    }
+
+    /// A client has repainted after being resized, so if it's being resized with the pointer, it can be sent
+    /// its next size straight away.
+    pub(super) fn on_alarm_notify(&mut self, ev: sync::AlarmNotifyEvent) -> xcb::Result<()> {
+        let sync = ret_ok_if_none!(self
+            .clients
+            .values_mut()
+            .filter_map(|c| c.sync.as_mut())
+            .find(|s| s.alarm == Some(ev.alarm())));
+        sync.requested = None;
+        log::trace!(target: logging::LAYOUT, "Client reached sync counter value {:?}", ev.counter_value());
+
+        self.resize_dragged_window(false)
+    }
+
+    /// Free the X resources used to sync with a client which is no longer managed.
+    pub(super) fn free_sync_state(&self, sync: &SyncState) {
+        if let Some(alarm) = sync.alarm {
+            self.conn.send_request(&sync::DestroyAlarm { alarm });
+        }
+    }
+}
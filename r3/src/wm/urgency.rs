@@ -0,0 +1,179 @@
+use r3lib::WMEvent;
+use xcb::{x, Xid};
+
+use super::WindowManager;
+use crate::ret_ok_if_none;
+
+/// The `UrgencyHint` flag in `WM_HINTS`
+const URGENCY_HINT: u32 = 1 << 8;
+
+/// `_NET_WM_STATE` client message actions
+const NET_WM_STATE_REMOVE: u32 = 0;
+const NET_WM_STATE_ADD: u32 = 1;
+const NET_WM_STATE_TOGGLE: u32 = 2;
+
+impl<'a> WindowManager<'a> {
+    /// Is the window demanding attention, either with the ICCCM `WM_HINTS` urgency flag or the EWMH
+    /// `_NET_WM_STATE_DEMANDS_ATTENTION` state?
+    /// The window's `_NET_WM_STATE_DEMANDS_ATTENTION` state is cached in its client as a side effect.
+    fn get_urgency(&mut self, window: x::Window) -> xcb::Result<bool> {
+        let wm_hints = self.request_wm_hints(window);
+        let wm_state = self.get_net_wm_state(window);
+        let wm_hints = self.conn.wait_for_reply(wm_hints)?;
+        let wm_state = self.conn.wait_for_reply(wm_state)?;
+
+        let demands_attention = self.demands_attention(&wm_state);
+        if let Some(client) = self.clients.get_mut(&window) {
+            client.demands_attention = demands_attention;
+        }
+        Ok(self.is_urgent_from(&wm_hints, &wm_state))
+    }
+
+    /// Does a window's `WM_HINTS` and `_NET_WM_STATE` say it's demanding attention?
+    pub(super) fn is_urgent_from(&self, wm_hints: &x::GetPropertyReply, wm_state: &x::GetPropertyReply) -> bool {
+        let hinted = matches!(wm_hints.value::<u32>(), [flags, ..] if flags & URGENCY_HINT != 0);
+        hinted || self.demands_attention(wm_state)
+    }
+
+    /// Does a window's `_NET_WM_STATE` include `_NET_WM_STATE_DEMANDS_ATTENTION`?
+    pub(super) fn demands_attention(&self, wm_state: &x::GetPropertyReply) -> bool {
+        wm_state
+            .value::<x::Atom>()
+            .contains(&self.atoms.net_wm_state_demands_attention)
+    }
+
+    pub(super) fn get_net_wm_state(&self, window: x::Window) -> x::GetPropertyCookie {
+        self.conn.send_request(&x::GetProperty {
+            delete: false,
+            window,
+            property: self.atoms.wm_state,
+            r#type: x::ATOM_ATOM,
+            long_offset: 0,
+            long_length: u32::MAX,
+        })
+    }
+
+    /// Re-read whether the window is demanding attention. The focused window is never marked as urgent, since
+    /// it already has the user's attention.
+    pub(super) fn update_urgency(&mut self, window: x::Window) -> xcb::Result<()> {
+        if !self.clients.contains_key(&window) {
+            return Ok(());
+        }
+
+        let urgent = self.get_urgency(window)? && !self.is_focused(window);
+        match (urgent, self.is_urgent(window)) {
+            // Keep the oldest first, so `FocusUrgent` visits windows in the order they asked for attention
+            (true, false) => self.urgent_windows.push(window),
+            (false, true) => self.urgent_windows.retain(|w| *w != window),
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    pub(super) fn is_urgent(&self, window: x::Window) -> bool {
+        self.urgent_windows.contains(&window)
+    }
+
+    /// The events for IPC subscribers since this was last called. Like rendering, these are found by comparing
+    /// the window manager's state with what was last reported.
+    pub fn take_events(&mut self) -> Vec<WMEvent> {
+        let event = |window: &x::Window, urgent| WMEvent::Urgency {
+            id: window.resource_id(),
+            urgent,
+        };
+        let mut events = self
+            .reported_urgent
+            .iter()
+            .filter(|w| !self.urgent_windows.contains(w))
+            .map(|w| event(w, false))
+            .collect::<Vec<_>>();
+        events.extend(
+            self.urgent_windows
+                .iter()
+                .filter(|w| !self.reported_urgent.contains(w))
+                .map(|w| event(w, true)),
+        );
+
+        self.reported_urgent.clone_from(&self.urgent_windows);
+        events
+    }
+
+    /// The window has been focused, so it no longer needs attention. As required by EWMH, its
+    /// `_NET_WM_STATE_DEMANDS_ATTENTION` state is removed (the `WM_HINTS` flag belongs to the client, so is left).
+    /// This is called whenever focus is rendered, so X is only asked about windows known to have the state.
+    pub(super) fn clear_urgency(&mut self, window: x::Window) -> xcb::Result<()> {
+        self.urgent_windows.retain(|w| *w != window);
+        match self.clients.get_mut(&window) {
+            Some(client) if client.demands_attention => client.demands_attention = false,
+            _ => return Ok(()),
+        }
+
+        self.set_demands_attention(window, Some(false))
+    }
+
+    /// Add (`Some(true)`), remove (`Some(false)`) or toggle (`None`) the `_NET_WM_STATE_DEMANDS_ATTENTION` state.
+    /// The resulting `PropertyNotify` updates the window's urgency.
+    fn set_demands_attention(&self, window: x::Window, state: Option<bool>) -> xcb::Result<()> {
+        let attention = self.atoms.net_wm_state_demands_attention;
+        let mut atoms = self
+            .conn
+            .wait_for_reply(self.get_net_wm_state(window))?
+            .value::<x::Atom>()
+            .to_vec();
+        let has_attention = atoms.contains(&attention);
+        match state.unwrap_or(!has_attention) {
+            true if !has_attention => atoms.push(attention),
+            false if has_attention => atoms.retain(|a| *a != attention),
+            _ => return Ok(()),
+        }
+
+        // Unchecked, since if the window has gone away there's nothing to change
+        self.conn.send_request(&x::ChangeProperty {
+            mode: x::PropMode::Replace,
+            window,
+            property: self.atoms.wm_state,
+            r#type: x::ATOM_ATOM,
+            data: &atoms,
+        });
+
+        Ok(())
+    }
+
+    /// Handle a client's request to change its `_NET_WM_STATE`. Only `_NET_WM_STATE_DEMANDS_ATTENTION` is
+    /// supported for now.
+    pub(super) fn on_net_wm_state_request(&self, window: x::Window, data: [u32; 5]) -> xcb::Result<()> {
+        if !self.clients.contains_key(&window) {
+            return Ok(());
+        }
+
+        let [action, first, second, ..] = data;
+        let attention = self.atoms.net_wm_state_demands_attention.resource_id();
+        if first != attention && second != attention {
+            return Ok(());
+        }
+
+        match action {
+            NET_WM_STATE_REMOVE => self.set_demands_attention(window, Some(false)),
+            NET_WM_STATE_ADD => self.set_demands_attention(window, Some(true)),
+            NET_WM_STATE_TOGGLE => self.set_demands_attention(window, None),
+            _ => Ok(()),
+        }
+    }
+
+    /// Focus the window which has been demanding attention the longest, switching to its workspace if needed. If
+    /// it's hidden in the scratchpad, it's shown on the current workspace instead.
+    pub(super) fn focus_urgent(&mut self) -> xcb::Result<()> {
+        let window = *ret_ok_if_none!(self.urgent_windows.first());
+        let client = ret_ok_if_none!(self.clients.get(&window));
+        if client.scratchpad_hidden {
+            return self.show_from_scratchpad(window);
+        }
+        let workspace = client.workspace;
+
+        self.switch_workspace(workspace)?;
+        self.focus_window(Some(window));
+
+        Ok(())
+    }
+}
@@ -1,147 +1,247 @@
 use xcb::{x, Xid};
 
+use super::client::Client;
+use super::decorations::window_title;
+use super::focus::accepts_input;
 use super::masks::MASKS;
 use super::WindowManager;
 use crate::point::Point;
-use crate::ret_ok_if_none;
 use crate::window_geometry::WindowGeometry;
+use crate::{logging, ret_ok_if_none};
 
 impl<'a> WindowManager<'a> {
     pub(super) fn get_frame_and_window(&self, target: x::Window) -> Option<(x::Window, x::Window)> {
         if let Some(frame) = self.framed_clients.get_by_left(&target) {
             Some((target, *frame))
-        } else if let Some(window) = self.framed_clients.get_by_right(&target) {
-            Some((*window, target))
         } else {
-            None
+            self.framed_clients
+                .get_by_right(&target)
+                .map(|window| (*window, target))
         }
     }
 
+    /// Start managing a window by reparenting it into a frame. Nothing here waits on the X server until all the
+    /// requests have been sent, so framing a window takes a couple of round trips rather than one per request.
     pub(super) fn frame_window(
         &mut self,
         window: x::Window,
         existed_before_wm: bool,
     ) -> xcb::Result<Option<x::Window>> {
         // Get window attributes
-        let geo = self.conn.wait_for_reply(self.conn.send_request(&x::GetGeometry {
+        let geo = self.conn.send_request(&x::GetGeometry {
             drawable: x::Drawable::Window(window),
-        }))?;
+        });
+        let attrs = existed_before_wm.then(|| self.conn.send_request(&x::GetWindowAttributes { window }));
+        let geo = self.conn.wait_for_reply(geo)?;
 
         // If window was created before window manager started, we should frame
         // it only if it is visible and doesn't set override_redirect.
-        if existed_before_wm {
-            let attrs = self
-                .conn
-                .wait_for_reply(self.conn.send_request(&x::GetWindowAttributes { window }))?;
+        if let Some(attrs) = attrs {
+            let attrs = self.conn.wait_for_reply(attrs)?;
             if attrs.override_redirect() || attrs.map_state() != x::MapState::Viewable {
                 return Ok(None);
             }
         }
 
-        // Create frame
+        // Create frame, leaving room for the title bar above the window
+        let title_bar_height = self.theme.title_bar_height;
         let frame = self.conn.generate_id();
         let root_window = self.get_root_window()?;
-        self.conn.send_and_check_request(&x::CreateWindow {
-            depth: x::COPY_FROM_PARENT as u8,   // TODO: ???
-            visual: x::COPY_FROM_PARENT as u32, // TODO: get from screen.root_visual()
-            wid: frame,
-            parent: root_window,
-            x: geo.x(),
-            y: geo.y(),
-            width: geo.width(),
-            height: geo.height(),
-            border_width: 10,
-            class: x::WindowClass::CopyFromParent,
-            value_list: &[
-                // Frame background color
-                // TODO: can I make this transparent in any way?
-                x::Cw::BackPixel(0x0000ff),
-                // Border pixel colour
-                x::Cw::BorderPixel(0xff0000),
+        let mut cookies = vec![
+            self.conn.send_request_checked(&x::CreateWindow {
+                depth: x::COPY_FROM_PARENT as u8, // TODO: ???
+                visual: x::COPY_FROM_PARENT,      // TODO: get from screen.root_visual()
+                wid: frame,
+                parent: root_window,
+                x: geo.x(),
+                y: geo.y(),
+                width: geo.width(),
+                height: geo.height() + title_bar_height,
+                border_width: self.theme.border_width,
+                class: x::WindowClass::CopyFromParent,
+                value_list: &[
+                    // Frame background color
+                    // TODO: can I make this transparent in any way?
+                    x::Cw::BackPixel(self.theme.unfocused.background.0),
+                    // Border pixel colour
+                    x::Cw::BorderPixel(self.theme.unfocused.border.0),
+                    // Which events to capture and send to the event loop
+                    // NOTE: we ignore enter events during re-parenting
+                    x::Cw::EventMask(MASKS.frame_window_events & !x::EventMask::ENTER_WINDOW),
+                ],
+            }),
+            // Set an atom on our frame to indicate that it is indeed a frame
+            self.conn.send_request_checked(&x::ChangeProperty {
+                mode: x::PropMode::Replace,
+                window: frame,
+                property: self.atoms.r3_frame,
+                r#type: x::ATOM_STRING,
+                data: b"1",
+            }),
+            // Start listening to window events
+            self.conn.send_request_checked(&x::ChangeWindowAttributes {
+                window,
                 // Which events to capture and send to the event loop
-                // NOTE: we ignore enter events during re-parenting
-                x::Cw::EventMask(MASKS.frame_window_events & !x::EventMask::ENTER_WINDOW),
-            ],
-        })?;
-
-        // Set an atom on our frame to indicate that it is indeed a frame
-        self.conn.send_and_check_request(&x::ChangeProperty {
-            mode: x::PropMode::Replace,
-            window: frame,
-            property: self.atoms.r3_frame,
-            r#type: x::ATOM_STRING,
-            data: b"1",
-        })?;
-
-        // Start listening to window events
-        self.conn.send_and_check_request(&x::ChangeWindowAttributes {
-            window,
-            // Which events to capture and send to the event loop
-            value_list: &[x::Cw::EventMask(MASKS.child_window_events)],
-        })?;
-
-        // Add window to save set
-        // TODO: doc why
-        self.conn.send_and_check_request(&x::ChangeSaveSet {
-            window,
-            mode: x::SetMode::Insert,
-        })?;
-
-        // Re-parent window into frame
-        self.conn.send_and_check_request(&x::ReparentWindow {
-            window,
-            parent: frame,
-            // Offset of client window within frame
-            x: 0,
-            y: 0,
-        })?;
-
-        // Map frame
-        self.conn.send_and_check_request(&x::MapWindow { window: frame })?;
+                value_list: &[x::Cw::EventMask(MASKS.child_window_events)],
+            }),
+            // Add window to save set
+            // TODO: doc why
+            self.conn.send_request_checked(&x::ChangeSaveSet {
+                window,
+                mode: x::SetMode::Insert,
+            }),
+            // Re-parent window into frame
+            self.conn.send_request_checked(&x::ReparentWindow {
+                window,
+                parent: frame,
+                // Offset of client window within frame
+                x: 0,
+                y: title_bar_height as i16,
+            }),
+            // Map frame
+            self.conn.send_request_checked(&x::MapWindow { window: frame }),
+        ];
 
-        // Save association b/w window and frame
-        self.framed_clients.insert(window, frame);
+        let buttons = self.create_title_bar_buttons(window, frame, geo.width(), &mut cookies);
 
-        // Button (mouse) handling
-        self.conn.send_and_check_request(&x::GrabButton {
+        // Button (mouse) handling: the grab is synchronous, so the pointer freezes on each click until we decide
+        // whether to handle it ourselves or replay it to the window (see `on_button_press`)
+        cookies.push(self.conn.send_request_checked(&x::GrabButton {
             grab_window: window,
             owner_events: false,
             event_mask: x::EventMask::BUTTON_PRESS | x::EventMask::BUTTON_RELEASE | x::EventMask::BUTTON_MOTION,
-            pointer_mode: x::GrabMode::Async,
+            pointer_mode: x::GrabMode::Sync,
             keyboard_mode: x::GrabMode::Async,
             confine_to: root_window,
             cursor: xcb::Xid::none(),
             button: x::ButtonIndex::Any,
             modifiers: x::ModMask::ANY,
-        })?;
+        }));
 
         // After mapping and re-parenting, configure all the events (including enter window)
-        self.conn.send_and_check_request(&x::ChangeWindowAttributes {
+        cookies.push(self.conn.send_request_checked(&x::ChangeWindowAttributes {
             window: frame,
             value_list: &[x::Cw::EventMask(MASKS.frame_window_events)],
-        })?;
+        }));
+
+        // Read the window's properties in the same round trip. By the time their replies arrive, X has handled
+        // every request above, so checking them doesn't wait any longer.
+        let (net_wm_name, wm_name) = self.request_window_title(window);
+        let wm_hints = self.request_wm_hints(window);
+        let protocols = self.request_protocols(window);
+        let wm_state = self.get_net_wm_state(window);
+        let sync_counter = self.request_sync_counter(window);
+        let properties = (|| -> xcb::Result<_> {
+            let wm_hints = self.conn.wait_for_reply(wm_hints)?;
+            let protocols = self.conn.wait_for_reply(protocols)?;
+            let protocols = protocols.value::<x::Atom>();
+            let wm_state = self.conn.wait_for_reply(wm_state)?;
+            Ok((
+                window_title(
+                    &self.conn.wait_for_reply(net_wm_name)?,
+                    &self.conn.wait_for_reply(wm_name)?,
+                ),
+                accepts_input(&wm_hints),
+                protocols.contains(&self.atoms.wm_take_focus),
+                self.is_urgent_from(&wm_hints, &wm_state),
+                self.demands_attention(&wm_state),
+                self.sync_state(protocols, &self.conn.wait_for_reply(sync_counter)?)?,
+            ))
+        })();
+        let checked = cookies
+            .into_iter()
+            .try_for_each(|cookie| self.conn.check_request(cookie));
+
+        let result = checked.map_err(xcb::Error::from).and(properties);
+        let (title, accepts_input, takes_focus, urgent, demands_attention, sync) = match result {
+            Ok(properties) => properties,
+            Err(e) => {
+                self.discard_frame(window, frame, &geo, buttons);
+                return Err(e);
+            }
+        };
+
+        // Save association b/w window and frame
+        self.framed_clients.insert(window, frame);
+        self.rendered.add_frame(window);
+        self.clients.insert(
+            window,
+            Client {
+                title,
+                buttons,
+                workspace: self.current_workspace,
+                accepts_input,
+                takes_focus,
+                demands_attention,
+                sync,
+                ..Default::default()
+            },
+        );
+        // The frame is drawn with the right colours when we next render
+        if urgent && !self.is_focused(window) {
+            self.urgent_windows.push(window);
+        }
 
         Ok(Some(frame))
     }
 
+    /// Clean up after failing to frame a window (e.g. because it went away while we were framing it). If the
+    /// window still exists, it has to be reparented back to the root before its frame is destroyed, since
+    /// destroying the frame would otherwise destroy the window too.
+    fn discard_frame(
+        &mut self,
+        window: x::Window,
+        frame: x::Window,
+        geo: &x::GetGeometryReply,
+        buttons: Vec<x::Window>,
+    ) {
+        for button in buttons {
+            self.title_bar_buttons.remove(&button);
+        }
+
+        // These fail if the window is already gone, which is fine
+        let cookies = [
+            self.conn.send_request_checked(&x::ReparentWindow {
+                window,
+                parent: geo.root(),
+                x: geo.x(),
+                y: geo.y(),
+            }),
+            self.conn.send_request_checked(&x::ChangeSaveSet {
+                window,
+                mode: x::SetMode::Delete,
+            }),
+            self.conn.send_request_checked(&x::DestroyWindow { window: frame }),
+        ];
+        for cookie in cookies {
+            let _ = self.conn.check_request(cookie);
+        }
+    }
+
     pub(super) fn unframe_window(&mut self, target: x::Window) -> xcb::Result<()> {
         let (window, frame) = ret_ok_if_none!(self.get_frame_and_window(target));
 
         // If it was the frame that was unmapped, then we don't need to do anything.
         if target == frame {
-            self.framed_clients.remove_by_right(&frame);
-            if self.focused_window == Some(frame) {
-                self.focused_window = None;
+            // Unless we unmapped it ourselves to hide it, in which case we're still managing it
+            if let Some(client) = self.clients.get_mut(&window) {
+                if client.ignore_unmaps > 0 {
+                    client.ignore_unmaps -= 1;
+                    return Ok(());
+                }
             }
 
+            self.framed_clients.remove_by_right(&frame);
+            self.forget_client(window);
+
             return Ok(());
         }
 
         // Unmap frame
         self.conn.send_and_check_request(&x::UnmapWindow { window: frame })?;
 
-        // Re-parent client window back to root
-        // FIXME: when checked this and others below error with BadWindow(3)
+        // Re-parent client window back to root. If the window was destroyed (rather than just unmapped) then
+        // these fail with BadWindow, so we don't wait to check them
         self.conn.send_request_checked(&x::ReparentWindow {
             window,
             parent: self.get_root_window()?,
@@ -161,30 +261,53 @@ impl<'a> WindowManager<'a> {
 
         // Drop window->frame association
         self.framed_clients.remove_by_left(&window);
-
-        // If it was the focused window, remove it
-        if self.focused_window == Some(window) || self.focused_window == Some(frame) {
-            self.focused_window = None;
-        }
+        self.forget_client(window);
 
         self.conn.flush()?;
 
         Ok(())
     }
 
-    fn supports_wm_delete_window(&self, window: x::Window) -> xcb::Result<bool> {
-        // Check if the window has declared support for WM_DELETE_WINDOW
-        let property = self.conn.wait_for_reply(self.conn.send_request(&x::GetProperty {
+    /// Drop all state kept for a client, including its title bar buttons (which are destroyed with the frame).
+    /// If it was focused, the previously focused window is focused instead.
+    pub(super) fn forget_client(&mut self, window: x::Window) {
+        self.forget_focus(window);
+        self.urgent_windows.retain(|w| *w != window);
+        self.scratchpad.retain(|w| *w != window);
+        self.rendered.remove_frame(window);
+        if let Some(client) = self.clients.remove(&window) {
+            if let Some(sync) = &client.sync {
+                self.free_sync_state(sync);
+            }
+            for button in client.buttons {
+                self.title_bar_buttons.remove(&button);
+                if self.hovered_button == Some(button) {
+                    self.hovered_button = None;
+                }
+                if self.pressed_button == Some(button) {
+                    self.pressed_button = None;
+                }
+            }
+        }
+    }
+
+    /// Check if the window has declared support for a protocol in WM_PROTOCOLS (e.g., WM_DELETE_WINDOW)
+    pub(super) fn supports_protocol(&self, window: x::Window, protocol: x::Atom) -> xcb::Result<bool> {
+        let property = self.conn.wait_for_reply(self.request_protocols(window))?;
+
+        let protocols = property.value::<x::Atom>();
+        Ok(protocols.contains(&protocol))
+    }
+
+    pub(super) fn request_protocols(&self, window: x::Window) -> x::GetPropertyCookie {
+        self.conn.send_request(&x::GetProperty {
             delete: false,
             window,
             property: self.atoms.wm_protocols,
             r#type: x::ATOM_ATOM,
             long_offset: 0,
             long_length: u32::MAX,
-        }))?;
-
-        let protocols = property.value::<x::Atom>();
-        Ok(protocols.contains(&self.atoms.wm_del_window))
+        })
     }
 
     pub(super) fn kill_window(&self, target: x::Window) -> xcb::Result<()> {
@@ -198,7 +321,7 @@ impl<'a> WindowManager<'a> {
             return Ok(());
         }
 
-        match self.supports_wm_delete_window(window)? {
+        match self.supports_protocol(window, self.atoms.wm_del_window)? {
             // If it does support it, send an event to kill it gracefully
             true => {
                 let data =
@@ -223,6 +346,7 @@ impl<'a> WindowManager<'a> {
     }
 
     pub(super) fn move_window(&self, window: x::Window, pos: Point) -> xcb::Result<()> {
+        log::trace!(target: logging::LAYOUT, "Moving {:?} to {:?}", window, pos);
         let value_list = &[x::ConfigWindow::X(pos.x.into()), x::ConfigWindow::Y(pos.y.into())];
 
         let id = match self.framed_clients.get_by_left(&window) {
@@ -232,14 +356,17 @@ impl<'a> WindowManager<'a> {
             None => window,
         };
 
-        // Move window
-        self.conn
-            .send_and_check_request(&x::ConfigureWindow { window: id, value_list })?;
+        // Move window. This is unchecked, since it happens for every step of a drag: errors are handled when they
+        // arrive with the next events.
+        self.conn.send_request(&x::ConfigureWindow { window: id, value_list });
 
         Ok(())
     }
 
-    pub(super) fn resize_window(&self, window: x::Window, rect: WindowGeometry) -> xcb::Result<()> {
+    /// Move and resize a window. If the window is framed, `rect` is the geometry of the frame, and the window
+    /// is fitted inside it below the title bar.
+    pub(super) fn resize_window(&mut self, window: x::Window, rect: WindowGeometry) -> xcb::Result<()> {
+        log::debug!(target: logging::LAYOUT, "Resizing {:?} to {:?}", window, rect);
         let mut value_list = vec![
             x::ConfigWindow::X(rect.x.into()),
             x::ConfigWindow::Y(rect.y.into()),
@@ -247,23 +374,72 @@ impl<'a> WindowManager<'a> {
             x::ConfigWindow::Height(rect.h.into()),
         ];
 
+        let mut height = rect.h;
         // Move frame if it has one
         if let Some(frame_id) = self.framed_clients.get_by_left(&window) {
-            self.conn.send_and_check_request(&x::ConfigureWindow {
+            self.conn.send_request(&x::ConfigureWindow {
                 window: *frame_id,
                 value_list: &value_list,
-            })?;
+            });
 
             // NOTE: x and y coords are relative to parent window (in this case the frame)
+            let title_bar_height = self.theme.title_bar_height;
             value_list[0] = x::ConfigWindow::X(0);
-            value_list[1] = x::ConfigWindow::Y(0);
+            value_list[1] = x::ConfigWindow::Y(title_bar_height.into());
+            height = rect.h.saturating_sub(title_bar_height).max(1);
+            value_list[3] = x::ConfigWindow::Height(height.into());
         }
 
-        // Move window
-        self.conn.send_and_check_request(&x::ConfigureWindow {
+        // Clients which support it are told they're being resized, so we can tell when they've repainted
+        self.send_sync_request(window, (rect.w, height));
+
+        // Move window (unchecked, like `move_window`)
+        self.conn.send_request(&x::ConfigureWindow {
             window,
             value_list: &value_list,
-        })?;
+        });
+
+        Ok(())
+    }
+
+    /// Maximize the window to fill the screen, or restore its previous geometry if it's already maximized.
+    pub(super) fn toggle_maximize(&mut self, window: x::Window) -> xcb::Result<()> {
+        let frame = *ret_ok_if_none!(self.framed_clients.get_by_left(&window));
+        let client = ret_ok_if_none!(self.clients.get_mut(&window));
+
+        match client.unmaximized_rect.take() {
+            Some(rect) => self.resize_window(window, rect)?,
+            None => {
+                let rect = self.get_window_rect(frame)?;
+                let root_rect = self.get_window_rect(self.get_root_window()?)?;
+
+                // Fill the screen inside the theme's gap, leaving room for the frame's border
+                let gap = self.theme.gap;
+                let inset = rect.bw * 2 + gap * 2;
+                self.resize_window(
+                    window,
+                    (
+                        gap as i16,
+                        gap as i16,
+                        root_rect.w.saturating_sub(inset),
+                        root_rect.h.saturating_sub(inset),
+                    )
+                        .into(),
+                )?;
+
+                if let Some(client) = self.clients.get_mut(&window) {
+                    client.unmaximized_rect = Some(rect);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Toggle whether the window is floating (floating windows are kept above all others).
+    pub(super) fn toggle_floating(&mut self, window: x::Window) -> xcb::Result<()> {
+        let client = ret_ok_if_none!(self.clients.get_mut(&window));
+        client.floating = !client.floating;
 
         Ok(())
     }
@@ -281,19 +457,45 @@ impl<'a> WindowManager<'a> {
         Ok((x, y, w, h, bw).into())
     }
 
-    pub(super) fn window_at_pos(&self, root: x::Window, pos: Point) -> xcb::Result<Option<x::Window>> {
+    /// Find the window that's visible at the given point on the screen: windows are checked from the top of the
+    /// stacking order down, skipping any that aren't mapped or that are override-redirect (popups, menus, etc).
+    /// If the point is over a frame, the frame's window is returned.
+    pub(super) fn window_at_point(&self, pos: Point) -> xcb::Result<Option<x::Window>> {
+        let root = self.get_root_window()?;
         let query_tree = self
             .conn
             .wait_for_reply(self.conn.send_request(&x::QueryTree { window: root }))?;
 
-        assert_eq!(root, query_tree.root());
+        // Send all the requests up front, rather than waiting for each window's replies in turn.
+        // `QueryTree` returns children in stacking order from bottom to top, so walk them in reverse.
+        let cookies = query_tree
+            .children()
+            .iter()
+            .rev()
+            .map(|window| {
+                (
+                    *window,
+                    self.conn.send_request(&x::GetWindowAttributes { window: *window }),
+                    self.conn.send_request(&x::GetGeometry {
+                        drawable: x::Drawable::Window(*window),
+                    }),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        for (window, attrs, geo) in cookies {
+            // The window may have been destroyed since the tree was queried
+            let (attrs, geo) = match (self.conn.wait_for_reply(attrs), self.conn.wait_for_reply(geo)) {
+                (Ok(attrs), Ok(geo)) => (attrs, geo),
+                _ => continue,
+            };
+            if attrs.map_state() != x::MapState::Viewable || attrs.override_redirect() {
+                continue;
+            }
 
-        for window in query_tree.children() {
-            // FIXME: check visibility and stacking order, etc - this kills the wrong window if another is placed above it
-            //  this entire function can be removed once we sort out "focus"
-            let win_rect = self.get_window_rect(*window)?;
-            if win_rect.contains(&pos) {
-                return Ok(Some(*window));
+            let rect: WindowGeometry = (geo.x(), geo.y(), geo.width(), geo.height(), geo.border_width()).into();
+            if rect.contains(&pos) {
+                return Ok(Some(*self.framed_clients.get_by_right(&window).unwrap_or(&window)));
             }
         }
 
@@ -0,0 +1,63 @@
+use xcb::x;
+
+use super::focus::FocusHistory;
+use super::WindowManager;
+use crate::ret_ok_if_none;
+
+/// Number of workspaces available
+pub const WORKSPACE_COUNT: usize = 10;
+
+#[derive(Debug, Default)]
+pub struct Workspace {
+    /// The windows on this workspace which have been focused, most recent last
+    pub focus_history: FocusHistory,
+}
+
+impl<'a> WindowManager<'a> {
+    /// Show another workspace, and focus its most recently focused window. Frames on the previous workspace are
+    /// unmapped, and the new workspace's frames are mapped, when we next render.
+    pub(super) fn switch_workspace(&mut self, index: usize) -> xcb::Result<()> {
+        if index >= WORKSPACE_COUNT || index == self.current_workspace {
+            return Ok(());
+        }
+
+        let previous = self.current_workspace;
+        self.current_workspace = index;
+        // Sticky windows follow the shown workspace, so they're never hidden
+        for (window, client) in self.clients.iter_mut() {
+            if client.sticky && client.workspace == previous {
+                client.workspace = index;
+                self.workspaces[previous].focus_history.remove(*window);
+                self.workspaces[index].focus_history.push(*window);
+            }
+        }
+
+        self.focus_previous();
+
+        Ok(())
+    }
+
+    /// Move a window to another workspace. It's hidden when we next render if that workspace isn't shown.
+    pub(super) fn move_to_workspace(&mut self, window: x::Window, index: usize) -> xcb::Result<()> {
+        if index >= WORKSPACE_COUNT {
+            return Ok(());
+        }
+
+        let client = ret_ok_if_none!(self.clients.get_mut(&window));
+        let previous = client.workspace;
+        if previous == index {
+            return Ok(());
+        }
+
+        // It becomes the most recently focused window on its new workspace
+        client.workspace = index;
+        self.workspaces[previous].focus_history.remove(window);
+        self.workspaces[index].focus_history.push(window);
+
+        if index != self.current_workspace && self.focused_window == Some(window) {
+            self.focus_previous();
+        }
+
+        Ok(())
+    }
+}
@@ -2,34 +2,34 @@ use std::cmp;
 
 use xcb::x::{
     self, ButtonPressEvent, ClientMessageEvent, ConfigureRequestEvent, EnterNotifyEvent, ExposeEvent, FocusInEvent,
-    FocusOutEvent, KeyPressEvent, LeaveNotifyEvent, MapRequestEvent, MotionNotifyEvent, PropertyNotifyEvent,
-    UnmapNotifyEvent,
+    FocusOutEvent, KeyPressEvent, KeyReleaseEvent, LeaveNotifyEvent, MapRequestEvent, MotionNotifyEvent,
+    PropertyNotifyEvent, UnmapNotifyEvent,
 };
 use xcb::BaseEvent;
 
 use super::{DragType, WindowManager};
+use crate::config::{FocusModel, MouseTarget};
 use crate::point::Point;
-use crate::ret_ok_if_none;
 use crate::window_geometry::Quadrant;
+use crate::{logging, ret_ok_if_none};
 
 impl<'a> WindowManager<'a> {
+    /// Handle an event (or an error) from the X server. Only an error with the connection itself is returned,
+    /// in which case r3 should exit.
     pub fn handle_event(&mut self, event: xcb::Result<xcb::Event>) -> xcb::Result<()> {
-        let event = match event {
-            Ok(event) => event,
-            Err(xcb::Error::Connection(err)) => {
-                panic!(
-                    "unexpected I/O error: {} {:?}",
-                    err,
-                    std::time::SystemTime::now()
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap()
-                        .as_micros()
-                );
-            }
-            Err(xcb::Error::Protocol(err)) => {
-                panic!("unexpected protocol error: {:#?}", err);
-            }
-        };
+        let result = event.and_then(|event| self.dispatch_event(event));
+        self.recover(result)?;
+
+        // Render even if handling the event failed, since some state may have been changed before the error
+        let result = self.render();
+        self.recover(result)
+    }
+
+    fn dispatch_event(&mut self, event: xcb::Event) -> xcb::Result<()> {
+        log::trace!(target: logging::X, "{:?}", event);
+        if let Some(time) = event_time(&event) {
+            self.last_event_time = time;
+        }
 
         match event {
             // We received a request to configure a window
@@ -58,6 +58,10 @@ impl<'a> WindowManager<'a> {
 
             // Handle client events
             xcb::Event::X(x::Event::ClientMessage(ev)) => self.on_client_message(ev)?,
+            // Another window manager is replacing us
+            xcb::Event::X(x::Event::SelectionClear(ev)) => self.on_selection_clear(ev)?,
+            // A client has repainted after being resized
+            xcb::Event::Sync(xcb::sync::Event::AlarmNotify(ev)) => self.on_alarm_notify(ev)?,
 
             // Ignored events
             xcb::Event::X(x::Event::ReparentNotify(_)) => {}
@@ -68,24 +72,20 @@ impl<'a> WindowManager<'a> {
             xcb::Event::X(x::Event::MapNotify(_)) => {}
 
             // TODO: handle all events!
-            _ => {
-                eprintln!("{:#?}", event);
-            }
+            _ => log::debug!(target: logging::X, "Unhandled event: {:?}", event),
         }
 
-        self.render()?;
-
         Ok(())
     }
 
-    /**
+    /*
      * X Client Events
      */
 
-    fn on_client_message(&self, ev: ClientMessageEvent) -> xcb::Result<()> {
+    fn on_client_message(&mut self, ev: ClientMessageEvent) -> xcb::Result<()> {
         // Send a sync message back
         if ev.r#type() == self.atoms.r3_sync {
-            eprintln!("R3_SYNC: {:?} ", ev.data());
+            log::trace!(target: logging::X, "R3_SYNC: {:?}", ev.data());
             let window = ev.window();
             self.conn.send_request(&x::SendEvent {
                 propagate: false,
@@ -95,18 +95,29 @@ impl<'a> WindowManager<'a> {
             });
             self.conn.flush()?;
             Ok(())
+        } else if ev.r#type() == self.atoms.wm_state {
+            match ev.data() {
+                x::ClientMessageData::Data32(data) => self.on_net_wm_state_request(ev.window(), data),
+                _ => Ok(()),
+            }
+        } else if ev.r#type() == self.atoms.net_startup_info_begin || ev.r#type() == self.atoms.net_startup_info {
+            if let x::ClientMessageData::Data8(data) = ev.data() {
+                let begin = ev.r#type() == self.atoms.net_startup_info_begin;
+                self.on_startup_info(ev.window(), &data, begin);
+            }
+            Ok(())
         } else {
             Ok(())
         }
     }
 
-    /**
+    /*
      * X Window Events
      */
 
-    fn on_configure_request(&self, ev: ConfigureRequestEvent) -> xcb::Result<()> {
+    fn on_configure_request(&mut self, ev: ConfigureRequestEvent) -> xcb::Result<()> {
         let window = ev.window();
-        let value_list = [
+        let mut value_list = vec![
             x::ConfigWindow::X(ev.x() as i32),
             x::ConfigWindow::Y(ev.y() as i32),
             x::ConfigWindow::Width(ev.width() as u32),
@@ -119,10 +130,30 @@ impl<'a> WindowManager<'a> {
 
         // If we've already framed this window, also update the frame
         if let Some(frame_id) = self.framed_clients.get_by_left(&window) {
+            // The frame keeps its own border, and makes room for the title bar
+            let title_bar_height = self.theme.title_bar_height;
+            let mut frame_values = vec![
+                x::ConfigWindow::X(ev.x() as i32),
+                x::ConfigWindow::Y(ev.y() as i32),
+                x::ConfigWindow::Width(ev.width() as u32),
+                x::ConfigWindow::Height(u32::from(ev.height()) + u32::from(title_bar_height)),
+            ];
+            // Only restack the frame if the client asked to be restacked
+            let restack = ev.value_mask().contains(x::ConfigWindowMask::STACK_MODE);
+            if restack {
+                frame_values.push(x::ConfigWindow::StackMode(ev.stack_mode()));
+            }
             self.conn.send_and_check_request(&x::ConfigureWindow {
                 window: *frame_id,
-                value_list: &value_list,
+                value_list: &frame_values,
             })?;
+            if restack {
+                self.rendered.invalidate_stacking();
+            }
+
+            // NOTE: x and y coords are relative to parent window (in this case the frame)
+            value_list[0] = x::ConfigWindow::X(0);
+            value_list[1] = x::ConfigWindow::Y(title_bar_height.into());
         }
 
         // Pass request straight through to the X server for window
@@ -140,13 +171,21 @@ impl<'a> WindowManager<'a> {
 
         // Re-parent the window with a frame if needed
         let window = ev.window();
-        let frame = self.frame_window(window, false)?;
+        self.frame_window(window, false)?;
+        self.place_launched_window(window)?;
+        let focus = self.apply_rules(window)?;
 
         // Allow the window to be mapped
         self.conn.send_and_check_request(&x::MapWindow { window })?;
 
-        // Focus the newly mapped window or frame if one was created
-        self.focused_window = frame.or(Some(window));
+        // Focus the newly mapped window, unless a rule put it elsewhere or asked for it not to be
+        let is_shown = self
+            .clients
+            .get(&window)
+            .is_some_and(|c| c.workspace == self.current_workspace);
+        if focus && is_shown {
+            self.focus_window(Some(window));
+        }
 
         Ok(())
     }
@@ -168,22 +207,30 @@ impl<'a> WindowManager<'a> {
         Ok(())
     }
 
-    /**
+    /*
      * Key Events
      */
 
     // TODO: remove hardcoded values when configuration is available
     fn on_key_press(&mut self, ev: KeyPressEvent) -> xcb::Result<()> {
+        if self.on_cycle_key_press(&ev)? {
+            return Ok(());
+        }
+
+        // MODIFIER + TAB (on qwerty) - cycle focus, backwards with SHIFT
+        if ev.state().contains(self.config.cycle_modifier.key_but_mask()) && ev.detail() == 0x17 {
+            return self.cycle_focus(!ev.state().contains(x::KeyButMask::SHIFT));
+        }
+
         // CTRL + SHIFT + Q - kill window manager
         // TODO: this has to be fired on a window
         if ev.state().contains(x::KeyButMask::CONTROL | x::KeyButMask::SHIFT) && ev.detail() == 0x18 {
-            self.ev_queue.lock().unwrap().push(r3lib::R3Command::Exit);
-            self.ev_waker.wake().unwrap();
+            self.queue_command(r3lib::R3Command::Exit);
             return Ok(());
         }
 
         // TODO: we choose focused window by cursor right now, but that's not right (should be whichever has focus, or is active, etc)
-        if let Some(window) = self.window_at_pos(ev.root(), (ev.root_x(), ev.root_y()).into())? {
+        if let Some(window) = self.window_at_point((ev.root_x(), ev.root_y()).into())? {
             // CTRL + Q (on qwerty) - kill window
             if ev.state().contains(x::KeyButMask::CONTROL) && ev.detail() == 0x18 {
                 self.kill_window(window)?;
@@ -193,36 +240,69 @@ impl<'a> WindowManager<'a> {
         Ok(())
     }
 
-    fn on_key_release(&self, _ev: KeyPressEvent) -> xcb::Result<()> {
-        Ok(())
+    fn on_key_release(&mut self, ev: KeyReleaseEvent) -> xcb::Result<()> {
+        self.on_cycle_key_release(&ev)
     }
 
-    /**
+    /*
      * Mouse Events
      */
 
     fn on_button_press(&mut self, ev: ButtonPressEvent) -> xcb::Result<()> {
         let target = ev.event();
-        let (window, frame) = ret_ok_if_none!(self.get_frame_and_window(target));
 
-        // Start a drag if Ctrl is pressed
-        // TODO: configurable modifier
-        if ev.state().contains(x::KeyButMask::CONTROL) || target == frame {
-            self.drag_start = Some((ev.root_x(), ev.root_y()).into());
-            self.drag_start_frame_rect = Some(self.get_window_rect(frame)?);
+        // Title bar buttons are clicked when the mouse button is released
+        if self.title_bar_buttons.contains_key(&target) {
+            self.pressed_button = Some(target);
+            return Ok(());
         }
 
-        // Focus and raise window
-        self.focused_window = Some(window);
-        self.conn.send_and_check_request(&x::ConfigureWindow {
-            window: frame,
-            value_list: &[x::ConfigWindow::StackMode(x::StackMode::Above)],
-        })?;
+        // Work out what was clicked: the background, or a client window or part of its frame
+        let clicked = match target == self.get_root_window()? {
+            true => None,
+            false => Some(ret_ok_if_none!(self.get_frame_and_window(target))),
+        };
+        let mouse_target = match clicked {
+            None => MouseTarget::Root,
+            Some((window, _)) if target == window => MouseTarget::Window,
+            Some(_) if (0..self.theme.title_bar_height as i16).contains(&ev.event_y()) => MouseTarget::TitleBar,
+            Some(_) => MouseTarget::Frame,
+        };
+        let binding = self
+            .config
+            .mouse_bindings()
+            .iter()
+            .find(|b| b.matches(ev.detail(), ev.state(), mouse_target))
+            .cloned();
+
+        // Clicks on the window arrive through our synchronous grab, and the pointer is frozen until we allow
+        // events again: either keep the click for ourselves, or replay it so the window receives it as usual
+        if mouse_target == MouseTarget::Window {
+            self.conn.send_and_check_request(&x::AllowEvents {
+                mode: match binding.is_some() {
+                    true => x::Allow::AsyncPointer,
+                    false => x::Allow::ReplayPointer,
+                },
+                time: ev.time(),
+            })?;
+        }
 
-        Ok(())
+        // Focus and raise window (all focus models focus on click)
+        if let Some((window, frame)) = clicked {
+            self.focus_window(Some(window));
+            self.conn.send_and_check_request(&x::ConfigureWindow {
+                window: frame,
+                value_list: &[x::ConfigWindow::StackMode(x::StackMode::Above)],
+            })?;
+            self.rendered.invalidate_stacking();
+        }
+
+        match binding {
+            Some(binding) => self.run_mouse_action(&binding.action, clicked, Point::new(ev.root_x(), ev.root_y())),
+            None => Ok(()),
+        }
     }
 
-    // TODO: remove hardcoded values when configuration is available
     fn on_motion_notify(&mut self, ev: MotionNotifyEvent) -> xcb::Result<()> {
         let target = ev.event();
         let (window, _) = ret_ok_if_none!(self.get_frame_and_window(target));
@@ -231,20 +311,14 @@ impl<'a> WindowManager<'a> {
         let drag_start_frame_rect = ret_ok_if_none!(self.drag_start_frame_rect);
 
         let delta = Point::new(ev.root_x(), ev.root_y()) - drag_start;
-        let drag_type = ret_ok_if_none!(if ev.state().contains(x::KeyButMask::BUTTON1) {
-            Some(DragType::Move)
-        } else if ev.state().contains(x::KeyButMask::BUTTON3) {
-            Some(DragType::Resize)
-        } else {
-            None
-        });
+        let drag_type = ret_ok_if_none!(self.drag_type.as_ref());
 
         match drag_type {
             DragType::Move => self.move_window(
                 window,
                 (drag_start_frame_rect.x + delta.x, drag_start_frame_rect.y + delta.y).into(),
             )?,
-            DragType::Resize => self.resize_window(
+            DragType::Resize => self.drag_resize(
                 window,
                 match ret_ok_if_none!(drag_start_frame_rect.quadrant(&drag_start)) {
                     Quadrant::TopLeft => (
@@ -280,12 +354,28 @@ impl<'a> WindowManager<'a> {
     }
 
     fn on_button_release(&mut self, _ev: ButtonPressEvent) -> xcb::Result<()> {
-        self.drag_start = None;
+        // End the drag, and release the pointer grab we took to change the cursor
+        if self.drag_start.take().is_some() {
+            self.conn
+                .send_and_check_request(&x::UngrabPointer { time: x::CURRENT_TIME })?;
+        }
         self.drag_start_frame_rect = None;
+        self.drag_type = None;
+        // The window ends up at the drag's final size, however recently it was resized
+        self.resize_dragged_window(true)?;
+        self.last_drag_resize = None;
+
+        // Only click the button if the pointer is still over it
+        if let Some(button) = self.pressed_button.take() {
+            if self.hovered_button == Some(button) {
+                self.on_title_bar_button_click(button)?;
+            }
+        }
+
         Ok(())
     }
 
-    /**
+    /*
      * Window Events
      */
 
@@ -298,19 +388,50 @@ impl<'a> WindowManager<'a> {
             return Ok(());
         };
 
-        if self.config.focus_follows_mouse {
-            let target = ev.event();
-            self.focused_window = Some(*self.framed_clients.get_by_right(&target).unwrap_or(&target));
+        // Track hover state of title bar buttons
+        let target = ev.event();
+        if self.title_bar_buttons.contains_key(&target) {
+            self.hovered_button = Some(target);
+            return Ok(());
+        }
+
+        // Focus follows the pointer into windows, and in the strict model, out of them too
+        let is_root = target == self.get_root_window()?;
+        match (self.config.focus_model, is_root) {
+            (FocusModel::Click, _) => {}
+            (FocusModel::Sloppy, true) => {}
+            (FocusModel::StrictMouse, true) => self.focus_window(None),
+            (FocusModel::Sloppy | FocusModel::StrictMouse, false) => self.focus_window(Some(target)),
         }
 
         Ok(())
     }
 
-    fn on_leave_notify(&self, _ev: LeaveNotifyEvent) -> xcb::Result<()> {
+    fn on_leave_notify(&mut self, ev: LeaveNotifyEvent) -> xcb::Result<()> {
+        let target = ev.event();
+        if self.hovered_button == Some(target) {
+            self.hovered_button = None;
+        }
+
         Ok(())
     }
 
-    fn on_expose(&self, _ev: ExposeEvent) -> xcb::Result<()> {
+    fn on_expose(&mut self, ev: ExposeEvent) -> xcb::Result<()> {
+        // Only redraw once the last of a series of Expose events arrives
+        if ev.count() > 0 {
+            return Ok(());
+        }
+
+        // Title bars are redrawn when we next render
+        let target = ev.window();
+        if let Some(window) = self.framed_clients.get_by_right(&target) {
+            self.rendered.invalidate_title_bar(*window);
+        } else if let Some((window, _)) = self.title_bar_buttons.get(&target) {
+            self.rendered.invalidate_title_bar(*window);
+        } else if self.is_cycle_overlay(target) {
+            self.draw_cycle_overlay()?;
+        }
+
         Ok(())
     }
 
@@ -322,7 +443,43 @@ impl<'a> WindowManager<'a> {
         Ok(())
     }
 
-    fn on_property_notify(&self, _ev: PropertyNotifyEvent) -> xcb::Result<()> {
+    fn on_property_notify(&mut self, ev: PropertyNotifyEvent) -> xcb::Result<()> {
+        // Redraw the title bar when a window changes its title
+        if ev.atom() == x::ATOM_WM_NAME || ev.atom() == self.atoms.net_wm_name {
+            self.update_window_title(ev.window())?;
+        }
+
+        // Keep track of how the window wants to be focused
+        let is_focus_hint = ev.atom() == x::ATOM_WM_HINTS || ev.atom() == self.atoms.wm_protocols;
+        if is_focus_hint && self.clients.contains_key(&ev.window()) {
+            let (accepts_input, takes_focus) = self.get_focus_hints(ev.window())?;
+            if let Some(client) = self.clients.get_mut(&ev.window()) {
+                client.accepts_input = accepts_input;
+                client.takes_focus = takes_focus;
+            }
+        }
+
+        // Keep track of whether the window is demanding attention
+        if ev.atom() == x::ATOM_WM_HINTS || ev.atom() == self.atoms.wm_state {
+            self.update_urgency(ev.window())?;
+        }
+
         Ok(())
     }
 }
+
+/// The server time an event happened at, for events which have one
+fn event_time(event: &xcb::Event) -> Option<x::Timestamp> {
+    match event {
+        xcb::Event::X(x::Event::KeyPress(ev)) => Some(ev.time()),
+        xcb::Event::X(x::Event::KeyRelease(ev)) => Some(ev.time()),
+        xcb::Event::X(x::Event::ButtonPress(ev)) => Some(ev.time()),
+        xcb::Event::X(x::Event::ButtonRelease(ev)) => Some(ev.time()),
+        xcb::Event::X(x::Event::MotionNotify(ev)) => Some(ev.time()),
+        xcb::Event::X(x::Event::EnterNotify(ev)) => Some(ev.time()),
+        xcb::Event::X(x::Event::LeaveNotify(ev)) => Some(ev.time()),
+        xcb::Event::X(x::Event::PropertyNotify(ev)) => Some(ev.time()),
+        xcb::Event::X(x::Event::SelectionClear(ev)) => Some(ev.time()),
+        _ => None,
+    }
+}
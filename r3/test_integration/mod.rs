@@ -1,5 +1,8 @@
 use x_test_runner::XTestRunner;
 
+mod test_cli;
+mod test_focus;
+mod test_ipc;
 mod test_window;
 mod x_test_runner;
 
@@ -12,7 +15,13 @@ macro_rules! wm_test {
     ($name:ident, $func:expr) => {
         #[test]
         fn $name() {
-            $func(crate::X_TEST_RUNNER.test());
+            $func($crate::X_TEST_RUNNER.test());
+        }
+    };
+    ($name:ident, config = $config:expr, $func:expr) => {
+        #[test]
+        fn $name() {
+            $func($crate::X_TEST_RUNNER.test_with_config($config));
         }
     };
 }
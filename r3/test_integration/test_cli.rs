@@ -0,0 +1,64 @@
+use std::{env, fs};
+
+use crate::x_test_runner::r3_bin_path;
+
+fn check_config(name: &str, config: &str) -> std::process::Output {
+    let path = env::temp_dir().join(format!("r3-test-{}.toml", name));
+    fs::write(&path, config).unwrap();
+    std::process::Command::new(r3_bin_path())
+        .arg("--check-config")
+        .arg("--config")
+        .arg(&path)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn checks_valid_config() {
+    let output = check_config("valid", "theme = \"default\"\nexec = [\"true\"]\n");
+    assert!(output.status.success());
+}
+
+#[test]
+fn checks_invalid_config() {
+    // Errors are reported with their position in the file
+    let output = check_config("invalid", "theme = \"default\"\nexec = [\n");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("line 3"));
+
+    let output = check_config("unknown-theme", "theme = \"missing\"\n");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unknown theme"));
+
+    let output = check_config("unknown-log-target", "[log_levels]\nfokus = \"debug\"\n");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unknown log target"));
+}
+
+#[test]
+fn checks_missing_config() {
+    let path = env::temp_dir().join("r3-test-missing.toml");
+    let _ = fs::remove_file(&path);
+    let output = std::process::Command::new(r3_bin_path())
+        .arg("--check-config")
+        .arg("--config")
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("doesn't exist"));
+}
+
+#[test]
+fn fails_to_start_with_missing_config() {
+    // This is checked before connecting to X, so doesn't need a display
+    let path = env::temp_dir().join("r3-test-missing-start.toml");
+    let _ = fs::remove_file(&path);
+    let output = std::process::Command::new(r3_bin_path())
+        .arg("--config")
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("doesn't exist"));
+}
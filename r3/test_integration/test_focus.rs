@@ -0,0 +1,191 @@
+use r3lib::{R3Command, WMCommand, WMEvent, WindowInfo};
+use xcb::{x, Xid};
+
+use crate::wm_test;
+use crate::x_test_runner::XTestCase;
+
+wm_test!(focuses_previous_window_on_close, |t: XTestCase| {
+    let a = t.open_window((0, 0, 30, 30));
+    a.map();
+    let b = t.open_window((100, 100, 30, 30));
+    b.map();
+    t.sync();
+    assert_eq!(2, t.get_all_windows().len());
+
+    // Closing the focused window should focus the previous one, so it can be closed next
+    t.command(R3Command::WM(WMCommand::CloseWindow));
+    t.sync();
+    assert_eq!(1, t.get_all_windows().len());
+
+    t.command(R3Command::WM(WMCommand::CloseWindow));
+    t.sync();
+    assert_eq!(0, t.get_all_windows().len());
+});
+
+wm_test!(hides_windows_on_other_workspaces, |t: XTestCase| {
+    let w = t.open_window((0, 0, 30, 30));
+    w.map();
+    t.sync();
+
+    // Moving the window away hides its frame, but it's still managed
+    t.command(R3Command::WM(WMCommand::MoveToWorkspace { index: 1 }));
+    t.sync();
+    assert!(!w.get_frame().is_viewable());
+    assert_eq!(1, t.get_all_windows().len());
+
+    t.command(R3Command::WM(WMCommand::SwitchWorkspace { index: 1 }));
+    t.sync();
+    assert!(w.get_frame().is_viewable());
+    assert!(w.is_viewable());
+});
+
+wm_test!(maps_frames_when_switching_workspaces, |t: XTestCase| {
+    let a = t.open_window((0, 0, 30, 30));
+    a.map();
+    t.sync();
+    t.command(R3Command::WM(WMCommand::SwitchWorkspace { index: 1 }));
+    let b = t.open_window((100, 100, 30, 30));
+    b.map();
+    t.sync();
+
+    // Only the current workspace's frame is mapped, and switching back and forth never loses a window
+    for _ in 0..2 {
+        assert!(!a.get_frame().is_viewable());
+        assert!(b.get_frame().is_viewable());
+
+        t.command(R3Command::WM(WMCommand::SwitchWorkspace { index: 0 }));
+        t.sync();
+        assert!(a.get_frame().is_viewable() && a.is_viewable());
+        assert!(!b.get_frame().is_viewable());
+        assert_eq!(2, t.get_all_windows().len());
+
+        t.command(R3Command::WM(WMCommand::SwitchWorkspace { index: 1 }));
+        t.sync();
+    }
+
+    // Switching to the workspace that's already shown changes nothing
+    t.command(R3Command::WM(WMCommand::SwitchWorkspace { index: 1 }));
+    t.sync();
+    assert!(!a.get_frame().is_viewable());
+    assert!(b.get_frame().is_viewable());
+});
+
+wm_test!(focuses_urgent_window, |t: XTestCase| {
+    let a = t.open_window((0, 0, 30, 30));
+    a.map();
+    let b = t.open_window((100, 100, 30, 30));
+    b.map();
+    t.sync();
+
+    // Set the urgency flag in WM_HINTS on the window that isn't focused
+    t.conn
+        .send_and_check_request(&x::ChangeProperty {
+            mode: x::PropMode::Replace,
+            window: a.id,
+            property: x::ATOM_WM_HINTS,
+            r#type: x::ATOM_WM_HINTS,
+            data: &[1u32 << 8, 0, 0, 0, 0, 0, 0, 0, 0],
+        })
+        .unwrap();
+    t.sync();
+
+    let get_window = |id: u32| -> WindowInfo {
+        let windows: Vec<WindowInfo> = serde_json::from_str(&t.command(R3Command::GetWindows)).unwrap();
+        windows.into_iter().find(|w| w.id == id).unwrap()
+    };
+    let info = get_window(a.id.resource_id());
+    assert!(info.urgent && !info.focused);
+
+    // Focusing it means it no longer needs attention
+    t.command(R3Command::WM(WMCommand::FocusUrgent));
+    t.sync();
+    let info = get_window(a.id.resource_id());
+    assert!(!info.urgent && info.focused);
+});
+
+wm_test!(shows_urgent_scratchpad_window, |t: XTestCase| {
+    let a = t.open_window((0, 0, 30, 30));
+    a.map();
+    let b = t.open_window((100, 100, 30, 30));
+    b.map();
+    t.sync();
+    t.command(R3Command::WM(WMCommand::MoveToScratchpad));
+    t.sync();
+    assert!(!b.get_frame().is_viewable());
+
+    t.conn
+        .send_and_check_request(&x::ChangeProperty {
+            mode: x::PropMode::Replace,
+            window: b.id,
+            property: x::ATOM_WM_HINTS,
+            r#type: x::ATOM_WM_HINTS,
+            data: &[1u32 << 8, 0, 0, 0, 0, 0, 0, 0, 0],
+        })
+        .unwrap();
+    t.sync();
+
+    // Focusing a window hidden in the scratchpad brings it back out
+    t.command(R3Command::WM(WMCommand::FocusUrgent));
+    t.sync();
+    assert!(b.get_frame().is_viewable());
+    let windows: Vec<WindowInfo> = serde_json::from_str(&t.command(R3Command::GetWindows)).unwrap();
+    let info = windows.into_iter().find(|w| w.id == b.id.resource_id()).unwrap();
+    assert!(!info.urgent && info.focused);
+});
+
+wm_test!(sends_urgency_events, |t: XTestCase| {
+    let a = t.open_window((0, 0, 30, 30));
+    a.map();
+    let b = t.open_window((100, 100, 30, 30));
+    b.map();
+    t.sync();
+    let mut events = t.subscribe();
+
+    // Subscribers are told when the window that isn't focused starts demanding attention
+    t.conn
+        .send_and_check_request(&x::ChangeProperty {
+            mode: x::PropMode::Replace,
+            window: a.id,
+            property: x::ATOM_WM_HINTS,
+            r#type: x::ATOM_WM_HINTS,
+            data: &[1u32 << 8, 0, 0, 0, 0, 0, 0, 0, 0],
+        })
+        .unwrap();
+    let id = a.id.resource_id();
+    assert_eq!(WMEvent::Urgency { id, urgent: true }, t.next_event(&mut events));
+
+    // And when it stops, because it's been focused
+    t.command(R3Command::WM(WMCommand::FocusUrgent));
+    assert_eq!(WMEvent::Urgency { id, urgent: false }, t.next_event(&mut events));
+});
+
+wm_test!(cycles_focus_with_keyboard, |t: XTestCase| {
+    // Keycodes on the X server's default (qwerty) keymap
+    const ALT: u8 = 64;
+    const TAB: u8 = 23;
+    const NUM_LOCK: u8 = 77;
+
+    let a = t.open_window((0, 0, 30, 30));
+    a.map();
+    let b = t.open_window((100, 100, 30, 30));
+    b.map();
+    t.sync();
+
+    let focused = || -> u32 {
+        let windows: Vec<WindowInfo> = serde_json::from_str(&t.command(R3Command::GetWindows)).unwrap();
+        windows.into_iter().find(|w| w.focused).unwrap().id
+    };
+    assert_eq!(b.id.resource_id(), focused());
+
+    // Alt+Tab focuses the previously focused window
+    t.press_keys(&[ALT, TAB]);
+    t.sync();
+    assert_eq!(a.id.resource_id(), focused());
+
+    // And still does with NumLock on
+    t.press_keys(&[NUM_LOCK]);
+    t.press_keys(&[ALT, TAB]);
+    t.press_keys(&[NUM_LOCK]);
+    t.sync();
+    assert_eq!(b.id.resource_id(), focused());
+});
@@ -0,0 +1,82 @@
+use std::time::{Duration, Instant};
+use std::{fs, thread};
+
+use r3lib::{IpcErrorKind, IpcErrorReply, LogLevel, OkReply, R3Command, VersionInfo, WMCommand, WindowInfo};
+
+use crate::wm_test;
+use crate::x_test_runner::XTestCase;
+
+wm_test!(replies_with_parse_errors, |t: XTestCase| {
+    let reply = t.send_message(b"{\n  \"WM\": \"NotACommand\"\n}");
+    let reply: IpcErrorReply = serde_json::from_str(&reply).unwrap();
+    assert_eq!(IpcErrorKind::Parse, reply.error.kind);
+    assert_eq!(Some(2), reply.error.line);
+    assert!(reply.error.column.is_some());
+
+    // The window manager is still running
+    let windows: Vec<WindowInfo> = serde_json::from_str(&t.command(R3Command::GetWindows)).unwrap();
+    assert!(windows.is_empty());
+});
+
+wm_test!(rejects_large_messages, |t: XTestCase| {
+    let reply = t.send_message(&vec![b' '; 1024 * 1024]);
+    let reply: IpcErrorReply = serde_json::from_str(&reply).unwrap();
+    assert_eq!(IpcErrorKind::TooLarge, reply.error.kind);
+    assert_eq!(None, reply.error.line);
+});
+
+wm_test!(replies_to_queries_and_commands, |t: XTestCase| {
+    let version: VersionInfo = serde_json::from_str(&t.command(R3Command::GetVersion)).unwrap();
+    assert_eq!(env!("CARGO_PKG_VERSION"), version.version);
+
+    let config: serde_json::Value = serde_json::from_str(&t.command(R3Command::GetConfig)).unwrap();
+    assert!(config.get("theme").is_some());
+
+    let reply: OkReply =
+        serde_json::from_str(&t.command(R3Command::WM(WMCommand::CycleFocus { forward: true }))).unwrap();
+    assert!(reply.ok);
+});
+
+wm_test!(
+    reruns_exec_always_on_reload,
+    config = r#"exec_always = ["echo run >> \"$XDG_CONFIG_HOME/exec-always.log\""]"#,
+    |t: XTestCase| {
+        let log = t.config_home().join("exec-always.log");
+        let _ = fs::remove_file(&log);
+        let wait_for_runs = |runs: usize| {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while fs::read_to_string(&log).unwrap_or_default().lines().count() < runs {
+                assert!(Instant::now() < deadline, "exec_always didn't run {} times", runs);
+                thread::sleep(Duration::from_millis(10));
+            }
+        };
+
+        // Run when r3 starts, and again each time its configuration is reloaded
+        wait_for_runs(1);
+        t.command(R3Command::Reload);
+        wait_for_runs(2);
+    }
+);
+
+wm_test!(replies_with_reload_errors, |t: XTestCase| {
+    fs::write(t.config_home().join("r3/config.toml"), "exec = [\n").unwrap();
+    let reply: IpcErrorReply = serde_json::from_str(&t.command(R3Command::Reload)).unwrap();
+    assert_eq!(IpcErrorKind::Invalid, reply.error.kind);
+    assert!(reply.error.message.contains("failed to parse"));
+});
+
+wm_test!(rejects_unknown_log_targets, |t: XTestCase| {
+    let reply = t.command(R3Command::SetLogLevel {
+        level: LogLevel::Debug,
+        target: Some("bogus".into()),
+    });
+    let reply: IpcErrorReply = serde_json::from_str(&reply).unwrap();
+    assert_eq!(IpcErrorKind::Invalid, reply.error.kind);
+
+    let reply = t.command(R3Command::SetLogLevel {
+        level: LogLevel::Debug,
+        target: Some("focus".into()),
+    });
+    let reply: OkReply = serde_json::from_str(&reply).unwrap();
+    assert!(reply.ok);
+});
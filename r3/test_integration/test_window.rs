@@ -1,22 +1,23 @@
-use r3lib::{R3Command, WMCommand};
+use r3lib::{Direction, R3Command, WMCommand, WindowInfo};
 use xcb::{x, Xid};
 
 use crate::wm_test;
-use crate::x_test_runner::XTestCase;
+use crate::x_test_runner::{XTestCase, XWindow};
 
 wm_test!(maps_a_window, |t: XTestCase| {
     let w = t.open_window((0, 0, 30, 30));
     w.map();
     t.sync();
 
-    // Check the window is mapped
-    assert_eq!(w.rect(), (0, 0, 30, 30));
+    // Check the window is mapped (below the frame's title bar)
+    assert_eq!(w.rect(), (0, 20, 30, 30));
     assert_eq!(1, t.get_all_windows().len());
 
-    // Check a frame was created
+    // Check a frame was created, with room for the title bar
     let f = w.get_frame();
-    assert_eq!(true, f.is_frame());
+    assert!(f.is_frame());
     assert_eq!(10, f.border_width());
+    assert_eq!(f.rect(), (0, 0, 30, 50));
 });
 
 wm_test!(kills_window_when_no_support_wm_delete_window, |t: XTestCase| {
@@ -68,3 +69,254 @@ wm_test!(can_gracefully_kill_window, |t: XTestCase| {
     t.sync();
     assert_eq!(0, t.get_all_windows().len());
 });
+
+wm_test!(can_toggle_maximize_window, |t: XTestCase| {
+    let w = t.open_window((10, 10, 30, 30));
+    w.map();
+    t.sync();
+
+    // The frame should fill the screen (minus its border), and the window fills the frame below the title bar
+    t.command(R3Command::WM(WMCommand::ToggleMaximize));
+    t.sync();
+    assert_eq!(w.get_frame().rect(), (0, 0, 780, 580));
+    assert_eq!(w.rect(), (0, 20, 780, 560));
+
+    // Toggling again restores its previous geometry
+    t.command(R3Command::WM(WMCommand::ToggleMaximize));
+    t.sync();
+    assert_eq!(w.get_frame().rect(), (10, 10, 30, 50));
+    assert_eq!(w.rect(), (0, 20, 30, 30));
+});
+
+wm_test!(
+    maximizes_windows_inside_gap,
+    config = r##"
+        theme = "gaps"
+
+        [themes.gaps]
+        gap = 15
+    "##,
+    |t: XTestCase| {
+        let w = t.open_window((10, 10, 30, 30));
+        w.map();
+        t.sync();
+
+        t.command(R3Command::WM(WMCommand::ToggleMaximize));
+        t.sync();
+        assert_eq!(w.get_frame().rect(), (15, 15, 750, 550));
+    }
+);
+
+wm_test!(sends_sync_requests_from_the_counter_value, |t: XTestCase| {
+    let w = t.open_window((10, 10, 30, 30));
+    w.set_sync_counter(1000);
+    w.map();
+    t.sync();
+
+    // The client is asked to update its counter past the value it started at, before being resized
+    t.command(R3Command::WM(WMCommand::ToggleMaximize));
+    let [_, _, lo, hi, _] = t.wait_for_protocol_message(&w, t.atoms.net_wm_sync_request);
+    assert_eq!((1001, 0), (lo, hi));
+});
+
+wm_test!(
+    can_switch_theme,
+    config = r##"
+        [themes.thin]
+        border_width = 3
+        title_bar_height = 12
+
+        [themes.thin.focused]
+        border = "#00ff00"
+        background = "Dark Gray"
+        text = "black"
+        button = "#ccc"
+        button_hover = "white"
+        button_active = "gray"
+    "##,
+    |t: XTestCase| {
+        let w = t.open_window((0, 0, 30, 30));
+        w.map();
+        t.sync();
+        assert_eq!(10, w.get_frame().border_width());
+
+        // Frames should be re-laid out around the window with the new theme
+        t.command(R3Command::WM(WMCommand::SetTheme { name: "thin".into() }));
+        t.sync();
+        assert_eq!(3, w.get_frame().border_width());
+        assert_eq!(w.get_frame().rect(), (0, 0, 30, 42));
+        assert_eq!(w.rect(), (0, 12, 30, 30));
+    }
+);
+
+wm_test!(swaps_windows_by_direction, |t: XTestCase| {
+    let a = t.open_window((0, 0, 30, 30));
+    a.map();
+    let b = t.open_window((200, 0, 60, 60));
+    b.map();
+    t.sync();
+
+    // The most recently mapped window is focused, and swaps places with the one to its left
+    let (a_frame, b_frame) = (a.get_frame().rect(), b.get_frame().rect());
+    t.command(R3Command::WM(WMCommand::Swap {
+        direction: Direction::Left,
+    }));
+    t.sync();
+    assert_eq!(a.get_frame().rect(), b_frame);
+    assert_eq!(b.get_frame().rect(), a_frame);
+
+    // There's nothing further to the left, so nothing changes
+    t.command(R3Command::WM(WMCommand::Swap {
+        direction: Direction::Left,
+    }));
+    t.sync();
+    assert_eq!(b.get_frame().rect(), a_frame);
+});
+
+wm_test!(toggles_scratchpad_windows, |t: XTestCase| {
+    let w = t.open_window((0, 0, 100, 100));
+    w.map();
+    t.sync();
+
+    // The window is hidden, but still managed
+    t.command(R3Command::WM(WMCommand::MoveToScratchpad));
+    t.sync();
+    assert!(!w.get_frame().is_viewable());
+    assert_eq!(1, t.get_all_windows().len());
+
+    // Showing it centres it on the (800x600) screen
+    t.command(R3Command::WM(WMCommand::ScratchpadShow { criteria: None }));
+    t.sync();
+    let f = w.get_frame();
+    assert!(f.is_viewable());
+    let (x, y, width, height) = f.rect();
+    let border = f.border_width() as i16;
+    assert_eq!(
+        (x, y),
+        ((800 - width as i16) / 2 - border, (600 - height as i16) / 2 - border)
+    );
+
+    // It's focused, so showing it again hides it
+    t.command(R3Command::WM(WMCommand::ScratchpadShow { criteria: None }));
+    t.sync();
+    assert!(!w.get_frame().is_viewable());
+});
+
+wm_test!(shows_and_hides_scratchpad_windows_repeatedly, |t: XTestCase| {
+    let w = t.open_window((0, 0, 100, 100));
+    w.map();
+    t.sync();
+    t.command(R3Command::WM(WMCommand::MoveToScratchpad));
+    t.sync();
+
+    // Each show maps the frame (with the window in it) and each hide unmaps it, without the window being unmanaged
+    for _ in 0..3 {
+        t.command(R3Command::WM(WMCommand::ScratchpadShow { criteria: None }));
+        t.sync();
+        assert!(w.get_frame().is_viewable() && w.is_viewable());
+
+        t.command(R3Command::WM(WMCommand::ScratchpadShow { criteria: None }));
+        t.sync();
+        assert!(!w.get_frame().is_viewable());
+        assert_eq!(1, t.get_all_windows().len());
+    }
+
+    // A shown scratchpad window follows workspace switches, like any other window on the current workspace
+    t.command(R3Command::WM(WMCommand::ScratchpadShow { criteria: None }));
+    t.command(R3Command::WM(WMCommand::SwitchWorkspace { index: 1 }));
+    t.sync();
+    assert!(!w.get_frame().is_viewable());
+});
+
+wm_test!(
+    applies_window_rules,
+    config = r#"
+        [[rules]]
+        class = "Pinned"
+        workspace = 1
+    "#,
+    |t: XTestCase| {
+        let w = t.open_window((0, 0, 30, 30));
+        t.conn
+            .send_and_check_request(&x::ChangeProperty {
+                mode: x::PropMode::Replace,
+                window: w.id,
+                property: x::ATOM_WM_CLASS,
+                r#type: x::ATOM_STRING,
+                data: b"pinned\0Pinned\0",
+            })
+            .unwrap();
+        w.map();
+        t.sync();
+
+        // The window is managed, but on another workspace
+        assert_eq!(1, t.get_all_windows().len());
+        assert!(!w.get_frame().is_viewable());
+
+        t.command(R3Command::WM(WMCommand::SwitchWorkspace { index: 1 }));
+        t.sync();
+        assert!(w.get_frame().is_viewable());
+    }
+);
+
+wm_test!(places_launched_windows_on_their_workspace, |t: XTestCase| {
+    // An application is launched, and then the workspace is switched before its window appears
+    t.send_startup_info(r#"new: ID="launcher-test-1" NAME="Test Application" SCREEN=0"#);
+    t.command(R3Command::WM(WMCommand::SwitchWorkspace { index: 1 }));
+    t.sync();
+
+    let w = t.open_window((0, 0, 30, 30));
+    t.conn
+        .send_and_check_request(&x::ChangeProperty {
+            mode: x::PropMode::Replace,
+            window: w.id,
+            property: t.atoms.net_startup_id,
+            r#type: t.atoms.utf8_string,
+            data: b"launcher-test-1",
+        })
+        .unwrap();
+    w.map();
+    t.sync();
+    assert!(!w.get_frame().is_viewable());
+
+    t.command(R3Command::WM(WMCommand::SwitchWorkspace { index: 0 }));
+    t.sync();
+    assert!(w.get_frame().is_viewable());
+});
+
+wm_test!(replaces_running_wm, |mut t: XTestCase| {
+    let w = t.open_window((0, 0, 30, 30));
+    w.map();
+    t.sync();
+
+    // The running r3 exits cleanly, and the new one adopts its windows
+    assert!(t.replace_r3().success());
+    t.sync();
+    assert_eq!(1, t.get_all_windows().len());
+    assert!(w.get_frame().is_frame());
+});
+
+wm_test!(restarts_in_place, |t: XTestCase| {
+    let a = t.open_window((200, 0, 60, 60));
+    a.map();
+    t.sync();
+    let b = t.open_window((0, 0, 30, 30));
+    b.map();
+    t.sync();
+    t.command(R3Command::WM(WMCommand::MoveToWorkspace { index: 1 }));
+    t.command(R3Command::WM(WMCommand::ToggleFloating));
+    t.sync();
+
+    t.restart_r3();
+    t.sync();
+
+    // Windows keep their workspace, flags, geometry and focus
+    let windows: Vec<WindowInfo> = serde_json::from_str(&t.command(R3Command::GetWindows)).unwrap();
+    let info = |w: &XWindow| windows.iter().find(|i| i.id == w.id.resource_id()).unwrap();
+    assert_eq!(1, info(&b).workspace);
+    assert!(!b.get_frame().is_viewable());
+    assert_eq!(0, info(&a).workspace);
+    assert!(info(&a).floating);
+    assert!(info(&a).focused);
+    assert_eq!(a.get_frame().rect(), (200, 0, 60, 80));
+});
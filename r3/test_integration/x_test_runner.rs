@@ -1,17 +1,18 @@
 use core::panic;
 use std::fmt::Debug;
-use std::io::{Read, Write};
+use std::io::{BufRead, BufReader, Read, Write};
 use std::net::Shutdown;
 use std::os::unix::net::UnixStream;
-use std::process::{Child, Command, Stdio};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use std::{env, thread};
+use std::{env, fs, thread};
 
-use r3lib::R3Command;
+use r3lib::{R3Command, WMEvent};
 use rand::Rng;
-use xcb::Xid;
+use xcb::{BaseEvent, Xid};
 
 pub struct XTestRunner {
     display_num: AtomicUsize,
@@ -26,19 +27,38 @@ impl XTestRunner {
 
     pub fn test(&self) -> XTestCase {
         let n = self.display_num.fetch_add(1, Ordering::SeqCst);
-        XTestCase::start(n)
+        XTestCase::start(n, None)
+    }
+
+    pub fn test_with_config(&self, config: &str) -> XTestCase {
+        let n = self.display_num.fetch_add(1, Ordering::SeqCst);
+        XTestCase::start(n, Some(config))
     }
 }
 
+/// The r3 binary being tested
+pub fn r3_bin_path() -> PathBuf {
+    env::current_dir().unwrap().join("../target/debug/r3")
+}
+
 xcb::atoms_struct! {
     #[derive(Debug)]
     pub struct Atoms {
         pub wm_protocols     => b"WM_PROTOCOLS",
         pub wm_del_window    => b"WM_DELETE_WINDOW",
+        pub net_wm_sync_request => b"_NET_WM_SYNC_REQUEST",
+        pub net_wm_sync_request_counter => b"_NET_WM_SYNC_REQUEST_COUNTER",
 
         pub r3_sync          => b"R3_SYNC",
+        pub r3_pid           => b"R3_PID",
+        pub wm_s0            => b"WM_S0",
         pub r3_socket_path   => b"R3_SOCKET_PATH",
         pub r3_frame         => b"R3_FRAME",
+
+        pub utf8_string      => b"UTF8_STRING",
+        pub net_startup_id   => b"_NET_STARTUP_ID",
+        pub net_startup_info_begin => b"_NET_STARTUP_INFO_BEGIN",
+        pub net_startup_info => b"_NET_STARTUP_INFO",
     }
 }
 
@@ -58,10 +78,14 @@ pub struct XTestCase {
     r3_child: Child,
     /// The handle to the child process for the X server
     x_child: Child,
+    /// The X display r3 is running on
+    display: String,
+    /// The directory r3's configuration is in
+    config_home: PathBuf,
 }
 
 impl XTestCase {
-    fn start(display_num: usize) -> XTestCase {
+    fn start(display_num: usize, config: Option<&str>) -> XTestCase {
         // Spawn X server
         let display = format!(":{}", display_num);
         let (program, extra_args) = match env::var("TEST_ENABLE_XEPHYR") {
@@ -89,7 +113,11 @@ impl XTestCase {
             let mut attempt = 0;
             let max_attempts = 5;
             loop {
-                match xcb::Connection::connect_with_extensions(Some(&display), &[], &[]) {
+                match xcb::Connection::connect_with_extensions(
+                    Some(&display),
+                    &[xcb::Extension::Sync, xcb::Extension::Test],
+                    &[],
+                ) {
                     Ok(inner) => break inner,
                     Err(_) => {
                         if attempt == max_attempts {
@@ -106,17 +134,18 @@ impl XTestCase {
         };
 
         // Spawn r3
-        let r3_stdio = match env::var("TEST_ENABLE_R3_STDIO") {
-            Ok(_) => Stdio::inherit,
-            Err(_) => Stdio::null,
-        };
-        let r3_bin_path = env::current_dir().unwrap().join("../target/debug/r3");
-        let r3_child = Command::new(r3_bin_path)
-            .env("DISPLAY", display)
-            .stdout(r3_stdio())
-            .stderr(r3_stdio())
-            .spawn()
-            .unwrap();
+        // Give each r3 its own config directory, so the user's configuration doesn't affect tests
+        let config_home = env::temp_dir().join(format!("r3-test-{}", display_num));
+        let config_dir = config_home.join("r3");
+        fs::create_dir_all(&config_dir).unwrap();
+        match config {
+            Some(config) => fs::write(config_dir.join("config.toml"), config).unwrap(),
+            None => {
+                let _ = fs::remove_file(config_dir.join("config.toml"));
+            }
+        }
+
+        let r3_child = XTestCase::spawn_r3(&display, &config_home, &[]);
 
         // Intern the X atoms we need
         let atoms = {
@@ -139,12 +168,79 @@ impl XTestCase {
             atoms: Arc::new(atoms),
             r3_child,
             x_child,
+            display,
+            config_home,
             start: Instant::now(),
         };
         XTestCase::create_sync_window(&mut t);
         t
     }
 
+    fn spawn_r3(display: &str, config_home: &Path, args: &[&str]) -> Child {
+        let r3_stdio = match env::var("TEST_ENABLE_R3_STDIO") {
+            Ok(_) => Stdio::inherit,
+            Err(_) => Stdio::null,
+        };
+        Command::new(r3_bin_path())
+            .args(args)
+            .env("DISPLAY", display)
+            .env("XDG_CONFIG_HOME", config_home)
+            .stdout(r3_stdio())
+            .stderr(r3_stdio())
+            .spawn()
+            .unwrap()
+    }
+
+    /// Start another r3 with `--replace`, and wait for it to take over from the running one. Returns the exit status
+    /// of the replaced r3.
+    pub fn replace_r3(&mut self) -> ExitStatus {
+        let new_child = XTestCase::spawn_r3(&self.display, &self.config_home, &["--replace"]);
+        let mut old_child = std::mem::replace(&mut self.r3_child, new_child);
+        let status = old_child.wait().unwrap();
+
+        // Wait for the new r3 to announce itself on the root window
+        let pid = self.r3_child.id().to_string();
+        loop {
+            let reply = self
+                .conn
+                .wait_for_reply(self.conn.send_request(&xcb::x::GetProperty {
+                    delete: false,
+                    window: self.root,
+                    property: self.atoms.r3_pid,
+                    r#type: xcb::x::ATOM_STRING,
+                    long_offset: 0,
+                    long_length: 1024,
+                }))
+                .unwrap();
+            if reply.value::<u8>() == pid.as_bytes() {
+                break status;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Restart r3 in place, and wait for the new process to become the window manager
+    pub fn restart_r3(&self) {
+        let get_owner = || {
+            self.conn
+                .wait_for_reply(self.conn.send_request(&xcb::x::GetSelectionOwner {
+                    selection: self.atoms.wm_s0,
+                }))
+                .unwrap()
+                .owner()
+        };
+
+        // The new process owns the manager selection with a new window once it's ready
+        let owner = get_owner();
+        self.command(R3Command::Restart);
+        while {
+            let new_owner = get_owner();
+            new_owner == owner || new_owner.is_none()
+        } {
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
     // TODO: more ergonomic configuration here - use a vec of enums for Cw attrs?
     pub fn open_window(&self, rect: (i16, i16, u16, u16)) -> XWindow {
         self._open_window(rect, false)
@@ -155,7 +251,7 @@ impl XTestCase {
         self.conn
             .send_and_check_request(&xcb::x::CreateWindow {
                 depth: xcb::x::COPY_FROM_PARENT as u8,
-                visual: xcb::x::COPY_FROM_PARENT as u32,
+                visual: xcb::x::COPY_FROM_PARENT,
                 wid,
                 parent: self.root,
                 x,
@@ -181,7 +277,7 @@ impl XTestCase {
 
     fn create_sync_window(&mut self) {
         // Create a hidden un-managed window which will be used for syncing
-        if let None = self.sync_window {
+        if self.sync_window.is_none() {
             // Create window outside of viewport, and set override redirect (so WM doesn't manage it)
             let w = self._open_window((-15, -15, 10, 10), true);
             w.map();
@@ -217,17 +313,62 @@ impl XTestCase {
         eprintln!("[sync] wait: {}", magic);
         loop {
             let event = self.conn.wait_for_event().unwrap();
-            match event {
-                xcb::Event::X(xcb::x::Event::ClientMessage(ev)) => match ev.data() {
-                    xcb::x::ClientMessageData::Data32([_wid, n, _, _, _]) if n == magic => break,
-                    _ => {}
-                },
-                _ => {}
+            if let xcb::Event::X(xcb::x::Event::ClientMessage(ev)) = event {
+                if let xcb::x::ClientMessageData::Data32([_wid, n, _, _, _]) = ev.data() {
+                    if n == magic {
+                        break;
+                    }
+                }
             }
         }
         eprintln!("[sync] recv: {}", magic);
     }
 
+    /// Press keys (by keycode) in order, and then release them in reverse order, like a user pressing a shortcut
+    pub fn press_keys(&self, keys: &[u8]) {
+        let fake_input = |r#type, detail| {
+            self.conn
+                .send_and_check_request(&xcb::xtest::FakeInput {
+                    r#type,
+                    detail,
+                    time: xcb::x::CURRENT_TIME,
+                    root: self.root,
+                    root_x: 0,
+                    root_y: 0,
+                    deviceid: 0,
+                })
+                .unwrap();
+        };
+        for key in keys {
+            fake_input(xcb::x::KeyPressEvent::NUMBER as u8, *key);
+        }
+        for key in keys.iter().rev() {
+            fake_input(xcb::x::KeyReleaseEvent::NUMBER as u8, *key);
+        }
+    }
+
+    /// Wait for a window to be sent a `WM_PROTOCOLS` client message for `protocol`, and return its data. Other
+    /// events are dropped.
+    pub fn wait_for_protocol_message(&self, window: &XWindow, protocol: xcb::x::Atom) -> [u32; 5] {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            match self.conn.poll_for_event().unwrap() {
+                Some(xcb::Event::X(xcb::x::Event::ClientMessage(ev)))
+                    if ev.window() == window.id && ev.r#type() == self.atoms.wm_protocols =>
+                {
+                    if let xcb::x::ClientMessageData::Data32(data) = ev.data() {
+                        if data[0] == protocol.resource_id() {
+                            break data;
+                        }
+                    }
+                }
+                Some(_) => {}
+                None if Instant::now() > deadline => panic!("Timed out waiting for {:?} message", protocol),
+                None => thread::sleep(Duration::from_millis(10)),
+            }
+        }
+    }
+
     pub fn get_all_windows(&self) -> Vec<XWindow> {
         let query_tree = self
             .conn
@@ -236,7 +377,7 @@ impl XTestCase {
 
         query_tree
             .children()
-            .into_iter()
+            .iter()
             .filter(|id| **id != self.sync_window.unwrap())
             .map(|id| XWindow {
                 id: *id,
@@ -262,17 +403,77 @@ impl XTestCase {
         String::from_utf8(reply.value::<u8>().into()).unwrap()
     }
 
-    pub fn command(&self, command: R3Command) {
+    /// Broadcast a startup-notification message (e.g. `new: ID=foo`), like an application launcher would
+    pub fn send_startup_info(&self, message: &str) {
+        let sender = self.open_window((0, 0, 1, 1));
+        let mut bytes = message.as_bytes().to_vec();
+        bytes.push(0);
+
+        // Messages are sent in chunks of 20 bytes
+        for (i, chunk) in bytes.chunks(20).enumerate() {
+            let mut data = [0; 20];
+            data[..chunk.len()].copy_from_slice(chunk);
+            let r#type = match i {
+                0 => self.atoms.net_startup_info_begin,
+                _ => self.atoms.net_startup_info,
+            };
+            self.conn
+                .send_and_check_request(&xcb::x::SendEvent {
+                    propagate: false,
+                    destination: xcb::x::SendEventDest::Window(self.root),
+                    event_mask: xcb::x::EventMask::PROPERTY_CHANGE,
+                    event: &xcb::x::ClientMessageEvent::new(sender.id, r#type, xcb::x::ClientMessageData::Data8(data)),
+                })
+                .unwrap();
+        }
+    }
+
+    pub fn command(&self, command: R3Command) -> String {
         eprintln!("[command] send: {:?}", command);
+        self.send_message(&serde_json::to_vec(&command).unwrap())
+    }
+
+    /// The directory r3 reads its configuration from, which is also `XDG_CONFIG_HOME` for the commands it runs
+    pub fn config_home(&self) -> &Path {
+        &self.config_home
+    }
+
+    /// Subscribe to events, returning once r3 has confirmed the subscription. The events are read from the returned
+    /// stream with `next_event`.
+    pub fn subscribe(&self) -> BufReader<UnixStream> {
         let mut c = UnixStream::connect(self.get_socket_path()).unwrap();
-        c.write_all(&serde_json::to_vec(&command).unwrap()).unwrap();
+        c.write_all(&serde_json::to_vec(&R3Command::Subscribe).unwrap())
+            .unwrap();
         c.shutdown(Shutdown::Write).unwrap();
+        c.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+        let mut events = BufReader::new(c);
+        let mut line = String::new();
+        events.read_line(&mut line).unwrap();
+        eprintln!("[subscribe] recv: {:?}", line);
+        events
+    }
+
+    /// Read the next event sent to a subscription
+    pub fn next_event(&self, events: &mut BufReader<UnixStream>) -> WMEvent {
+        let mut line = String::new();
+        events.read_line(&mut line).unwrap();
+        eprintln!("[event] recv: {:?}", line);
+        serde_json::from_str(&line).unwrap()
+    }
+
+    /// Send a raw IPC message, which may not be a valid command, and return the reply
+    pub fn send_message(&self, message: &[u8]) -> String {
+        let mut c = UnixStream::connect(self.get_socket_path()).unwrap();
+        // The server may stop reading (and close the connection) before the whole message is sent
+        let _ = c.write_all(message);
+        let _ = c.shutdown(Shutdown::Write);
 
         // Read response
         let mut buffer = String::new();
         c.read_to_string(&mut buffer).unwrap();
         eprintln!("[command] recv: {:?}", buffer);
-        // TODO: return response once a format for it exists
+        buffer
     }
 }
 
@@ -299,12 +500,54 @@ impl XWindow {
             .unwrap();
     }
 
+    /// Support `_NET_WM_SYNC_REQUEST` with a new XSync counter, which starts at `value`. This should be done before
+    /// the window is mapped.
+    pub fn set_sync_counter(&self, value: i64) {
+        let counter = self.conn.generate_id();
+        self.conn
+            .send_and_check_request(&xcb::sync::CreateCounter {
+                id: counter,
+                initial_value: xcb::sync::Int64 {
+                    hi: (value >> 32) as i32,
+                    lo: value as u32,
+                },
+            })
+            .unwrap();
+        self.conn
+            .send_and_check_request(&xcb::x::ChangeProperty {
+                mode: xcb::x::PropMode::Replace,
+                window: self.id,
+                property: self.atoms.wm_protocols,
+                r#type: xcb::x::ATOM_ATOM,
+                data: &[self.atoms.net_wm_sync_request],
+            })
+            .unwrap();
+        self.conn
+            .send_and_check_request(&xcb::x::ChangeProperty {
+                mode: xcb::x::PropMode::Replace,
+                window: self.id,
+                property: self.atoms.net_wm_sync_request_counter,
+                r#type: xcb::x::ATOM_CARDINAL,
+                data: &[counter.resource_id()],
+            })
+            .unwrap();
+    }
+
     pub fn close(self) {
         self.conn
             .send_and_check_request(&xcb::x::DestroyWindow { window: self.id })
             .unwrap();
     }
 
+    pub fn is_viewable(&self) -> bool {
+        let attrs = self
+            .conn
+            .wait_for_reply(self.conn.send_request(&xcb::x::GetWindowAttributes { window: self.id }))
+            .unwrap();
+
+        attrs.map_state() == xcb::x::MapState::Viewable
+    }
+
     pub fn is_frame(&self) -> bool {
         let reply = self
             .conn
@@ -1,14 +1,74 @@
-use clap_derive::Subcommand;
+use clap_derive::{ArgEnum, Subcommand};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize, Subcommand)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ArgEnum)]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+/// How much r3 logs: each level includes those above it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ArgEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Subcommand)]
 pub enum WMCommand {
     /// Close the currently focused window
     CloseWindow,
+    /// Maximize the currently focused window, or restore it if it's already maximized
+    ToggleMaximize,
+    /// Toggle whether the currently focused window is floating
+    ToggleFloating,
+    /// Switch to another theme defined in the configuration
+    SetTheme { name: String },
+    /// Show another workspace (numbered from 0)
+    SwitchWorkspace { index: usize },
+    /// Show the next (or previous, without `--forward`) workspace
+    CycleWorkspace {
+        #[clap(long)]
+        forward: bool,
+    },
+    /// Move the currently focused window to another workspace (numbered from 0)
+    MoveToWorkspace { index: usize },
+    /// Hide the currently focused window in the scratchpad
+    MoveToScratchpad,
+    /// Show a window from the scratchpad floating in the middle of the screen, or hide it again if it's focused.
+    /// Without criteria, scratchpad windows are shown in the order they were added.
+    ScratchpadShow {
+        /// Only show a window whose title contains this text
+        criteria: Option<String>,
+    },
+    /// Focus the next (or previous, without `--forward`) most recently focused window on the current workspace
+    CycleFocus {
+        #[clap(long)]
+        forward: bool,
+    },
+    /// Focus the nearest window in a direction from the currently focused window
+    FocusDirection {
+        #[clap(arg_enum)]
+        direction: Direction,
+    },
+    /// Focus the window which has been demanding attention the longest
+    FocusUrgent,
+    /// Swap the position and size of the currently focused window with the nearest window in a direction
+    Swap {
+        #[clap(arg_enum)]
+        direction: Direction,
+    },
     // TODO: get window state, etc
 }
 
-#[derive(Debug, Serialize, Deserialize, Subcommand)]
+#[derive(Debug, Clone, Serialize, Deserialize, Subcommand)]
 pub enum R3Command {
     /// Commands specific to Window Management
     #[clap(subcommand)]
@@ -17,6 +77,105 @@ pub enum R3Command {
     GetVersion,
     /// Returns the current configuration
     GetConfig,
+    /// Returns a list of all managed windows
+    GetWindows,
+    /// Keep the connection open, and send each event (as a line of JSON) as it happens
+    Subscribe,
+    /// Run a shell command in the background
+    Exec { cmd: String },
+    /// Re-read the configuration file, and re-run its `exec_always` commands
+    Reload,
+    /// Restart r3 in place (e.g. after upgrading it), keeping the state of its windows
+    Restart,
+    /// Change how much r3 logs, either everywhere or for one subsystem (x, ipc, focus or layout)
+    SetLogLevel {
+        #[clap(arg_enum)]
+        level: LogLevel,
+        #[clap(long)]
+        target: Option<String>,
+    },
     /// Exit the app
     Exit,
 }
+
+/// A managed window, as returned by `R3Command::GetWindows`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WindowInfo {
+    /// The client window's X id
+    pub id: u32,
+    pub title: String,
+    pub workspace: usize,
+    pub focused: bool,
+    pub floating: bool,
+    /// Whether the window is demanding attention
+    pub urgent: bool,
+}
+
+/// Something which happened in the window manager, sent to clients after `R3Command::Subscribe`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WMEvent {
+    /// A window started or stopped demanding attention
+    Urgency {
+        /// The client window's X id
+        id: u32,
+        urgent: bool,
+    },
+}
+
+/// The reply to `R3Command::GetVersion`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VersionInfo {
+    pub version: String,
+}
+
+/// The reply to commands which don't return anything
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OkReply {
+    pub ok: bool,
+}
+
+/// The kind of problem an IPC request had
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IpcErrorKind {
+    /// The message wasn't a valid command
+    Parse,
+    /// The message was larger than the server accepts
+    TooLarge,
+    /// The server is already handling as many connections as it accepts
+    TooManyConnections,
+    /// The command's arguments weren't valid
+    Invalid,
+    /// The server failed to run the command
+    Internal,
+}
+
+/// An error sent back to an IPC client (as `{"error": {...}}`) instead of the command's reply
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IpcError {
+    pub kind: IpcErrorKind,
+    pub message: String,
+    /// For parse errors, the line of the message the error was found on (starting at 1)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    /// For parse errors, the column of the message the error was found on (starting at 1)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<usize>,
+}
+
+impl IpcError {
+    pub fn new(kind: IpcErrorKind, message: impl Into<String>) -> IpcError {
+        IpcError {
+            kind,
+            message: message.into(),
+            line: None,
+            column: None,
+        }
+    }
+}
+
+/// The wire format of an `IpcError`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IpcErrorReply {
+    pub error: IpcError,
+}